@@ -0,0 +1,308 @@
+//! Attribute macros for [`timeout-tracing`](https://docs.rs/timeout-tracing).
+//! Use them through the `macros` feature of `timeout-tracing`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ToTokens, quote, quote_spanned};
+use syn::{
+    Expr, ItemFn, LitStr, ReturnType, Type, parenthesized, parse_macro_input, spanned::Spanned,
+    token,
+};
+
+/// Limit the run time of an async function with `timeout_tracing::timeout`.
+///
+/// The function must return a `Result`. When the timeout elapses, `TimeoutElapsed` is converted
+/// into the error type of the function with [`From`].
+///
+/// Arguments:
+/// - `duration = "5s"` (required): the time limit, as a sequence of numbers with units `h`, `m`, `s`, `ms`, `us`
+///   or `ns`, e.g. `"1m30s"`;
+/// - `capture = <expr>`: the `CaptureTrace` implementation, `timeout_tracing::CaptureSpanTrace` by default;
+/// - `instrument` or `instrument(<args>)`: also add `#[tracing::instrument(<args>)]` to the function,
+///   so that each trace has at least the span of the function. This requires a dependency on `tracing`.
+///
+/// # Examples
+/// ```rust,ignore
+/// use timeout_tracing::{CaptureSpanAndStackTrace, traced_timeout};
+///
+/// #[traced_timeout(duration = "5s", capture = CaptureSpanAndStackTrace, instrument(skip(db)))]
+/// async fn load_user(db: &Db, id: u64) -> anyhow::Result<User> {
+///     db.query_user(id).await
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn traced_timeout(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("duration") {
+            let lit: LitStr = meta.value()?.parse()?;
+            args.duration = Some(parse_duration(&lit)?);
+            Ok(())
+        } else if meta.path.is_ident("capture") {
+            args.capture = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("instrument") {
+            let mut instrument_args = TokenStream2::new();
+            if meta.input.peek(token::Paren) {
+                let content;
+                parenthesized!(content in meta.input);
+                instrument_args = content.parse()?;
+            }
+            args.instrument = Some(instrument_args);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported traced_timeout argument"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemFn);
+    match expand(args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// Run an async test with `#[tokio::test]`, failing it if it does not complete within a time limit.
+///
+/// The test body is run with `timeout_tracing::run_test`: a subscriber with `tracing_error::ErrorLayer`
+/// is installed unless a global one is already set, and on timeout the test panics with the formatted
+/// `TimeoutElapsed`, which shows the await points the test hangs at.
+///
+/// Arguments:
+/// - `timeout = "30s"` (required): the time limit, in the same format as `duration` of [`macro@traced_timeout`];
+/// - `capture = <expr>`: the `CaptureTrace` implementation, `timeout_tracing::CaptureSpanTrace` by default;
+/// - all other arguments, e.g. `flavor = "multi_thread"`, are passed to `#[tokio::test]`.
+///
+/// Requires the `macros` and `test-util` features of `timeout-tracing`.
+///
+/// # Examples
+/// ```rust,ignore
+/// #[timeout_tracing::test(timeout = "30s", flavor = "multi_thread")]
+/// async fn does_not_deadlock() {
+///     let (a, b) = tokio::join!(lock_a_then_b(), lock_b_then_a());
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = TestArgs::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("timeout") {
+            let lit: LitStr = meta.value()?.parse()?;
+            args.timeout = Some(parse_duration(&lit)?);
+        } else if meta.path.is_ident("capture") {
+            args.capture = Some(meta.value()?.parse()?);
+        } else {
+            let path = meta.path;
+            let value: Expr = meta
+                .input
+                .parse::<token::Eq>()
+                .and_then(|_| meta.input.parse())?;
+            args.tokio_args.push(quote!(#path = #value));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemFn);
+    match expand_test(args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct TestArgs {
+    /// Seconds and nanoseconds
+    timeout: Option<(u64, u32)>,
+    capture: Option<Expr>,
+    /// Arguments passed to `#[tokio::test]`
+    tokio_args: Vec<TokenStream2>,
+}
+
+fn expand_test(args: TestArgs, item: ItemFn) -> syn::Result<TokenStream2> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            sig.fn_token.span(),
+            "#[timeout_tracing::test] can only be applied to async functions",
+        ));
+    }
+    let Some((secs, nanos)) = args.timeout else {
+        return Err(syn::Error::new(
+            sig.span(),
+            "#[timeout_tracing::test] requires the `timeout` argument, e.g. `timeout = \"30s\"`",
+        ));
+    };
+    let capture = args.capture.map_or_else(
+        || quote!(::timeout_tracing::CaptureSpanTrace),
+        ToTokens::into_token_stream,
+    );
+    let tokio_args = &args.tokio_args;
+    let fake_return = match &sig.output {
+        ReturnType::Default => fake_return(&quote!(())),
+        ReturnType::Type(_, ret) => fake_return(&ret.to_token_stream()),
+    };
+    let stmts = &block.stmts;
+    Ok(quote! {
+        #(#attrs)*
+        #[::tokio::test(#(#tokio_args),*)]
+        #vis #sig {
+            ::timeout_tracing::run_test(
+                ::core::time::Duration::new(#secs, #nanos),
+                #capture,
+                async move {
+                    #fake_return
+                    #(#stmts)*
+                },
+            )
+            .await
+        }
+    })
+}
+
+#[derive(Default)]
+struct Args {
+    /// Seconds and nanoseconds
+    duration: Option<(u64, u32)>,
+    capture: Option<Expr>,
+    instrument: Option<TokenStream2>,
+}
+
+fn expand(args: Args, item: ItemFn) -> syn::Result<TokenStream2> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            sig.fn_token.span(),
+            "#[traced_timeout] can only be applied to async functions",
+        ));
+    }
+    let ReturnType::Type(_, ret) = &sig.output else {
+        return Err(syn::Error::new(
+            sig.span(),
+            "#[traced_timeout] requires the function to return a Result",
+        ));
+    };
+    if !is_result(ret) {
+        return Err(syn::Error::new(
+            ret.span(),
+            "#[traced_timeout] requires the function to return a Result",
+        ));
+    }
+    let Some((secs, nanos)) = args.duration else {
+        return Err(syn::Error::new(
+            sig.span(),
+            "#[traced_timeout] requires the `duration` argument, e.g. `duration = \"5s\"`",
+        ));
+    };
+    let capture = args.capture.map_or_else(
+        || quote!(::timeout_tracing::CaptureSpanTrace),
+        ToTokens::into_token_stream,
+    );
+    let instrument = args
+        .instrument
+        .map(|instrument_args| quote!(#[::tracing::instrument(#instrument_args)]));
+    let stmts = &block.stmts;
+    let fake_return = fake_return(&ret.to_token_stream());
+    Ok(quote! {
+        #(#attrs)*
+        #instrument
+        #vis #sig {
+            match ::timeout_tracing::timeout(
+                ::core::time::Duration::new(#secs, #nanos),
+                #capture,
+                async move {
+                    #fake_return
+                    #(#stmts)*
+                },
+            )
+            .await
+            {
+                ::core::result::Result::Ok(result) => result,
+                ::core::result::Result::Err(elapsed) => {
+                    ::core::result::Result::Err(::core::convert::From::from(elapsed))
+                }
+            }
+        }
+    })
+}
+
+/// Fixes the return type of an async block before its body, the same way `#[tracing::instrument]` does.
+fn fake_return(ret: &TokenStream2) -> TokenStream2 {
+    quote_spanned! {ret.span()=>
+        #[allow(
+            unknown_lints,
+            unreachable_code,
+            clippy::diverging_sub_expression,
+            clippy::empty_loop,
+            clippy::needless_return
+        )]
+        if false {
+            let __timeout_tracing_fake_return: #ret = loop {};
+            return __timeout_tracing_fake_return;
+        }
+    }
+}
+
+/// Also accepts aliases like `io::Result<T>` or `anyhow::Result<T>`.
+fn is_result(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Result"),
+        Type::Group(group) => is_result(&group.elem),
+        Type::Paren(paren) => is_result(&paren.elem),
+        _ => false,
+    }
+}
+
+/// Parses durations like `"5s"`, `"250ms"` or `"1m30s"` into seconds and nanoseconds.
+fn parse_duration(lit: &LitStr) -> syn::Result<(u64, u32)> {
+    let error = || {
+        syn::Error::new(
+            lit.span(),
+            "invalid duration, expected a sequence of numbers with units `h`, `m`, `s`, `ms`, `us` or `ns`, e.g. \"1m30s\"",
+        )
+    };
+    let value = lit.value();
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err(error());
+    }
+    let mut total_nanos: u128 = 0;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+        let number: u128 = rest[..digits].parse().map_err(|_| error())?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let nanos_per_unit: u128 = match &rest[..unit_len] {
+            "h" => 3_600_000_000_000,
+            "m" => 60_000_000_000,
+            "s" => 1_000_000_000,
+            "ms" => 1_000_000,
+            "us" => 1_000,
+            "ns" => 1,
+            _ => return Err(error()),
+        };
+        rest = &rest[unit_len..];
+        total_nanos = number
+            .checked_mul(nanos_per_unit)
+            .and_then(|nanos| total_nanos.checked_add(nanos))
+            .ok_or_else(error)?;
+    }
+    let secs = u64::try_from(total_nanos / 1_000_000_000).map_err(|_| error())?;
+    let nanos = u32::try_from(total_nanos % 1_000_000_000).map_err(|_| error())?;
+    Ok((secs, nanos))
+}
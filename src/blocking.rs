@@ -0,0 +1,444 @@
+use std::{
+    ffi::c_void,
+    mem,
+    ops::Range,
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    filter::function,
+    stack::{StackFrame, StackTrace},
+    stats::BlockedPoll,
+};
+
+/// The signal sent to a thread that is blocked in `poll` to capture its stack.
+/// It is ignored by default and is not used by Tokio or the standard library.
+const SIGNAL: libc::c_int = libc::SIGURG;
+
+/// How long to wait for the blocked thread to handle the signal.
+const SIGNAL_TIMEOUT: Duration = Duration::from_millis(100);
+
+const MAX_FRAMES: usize = 256;
+
+/// Frames written by the signal handler. The handler can't allocate or lock,
+/// so the instruction pointers are stored into preallocated atomics.
+static SIGNAL_FRAMES: [AtomicUsize; MAX_FRAMES] = [const { AtomicUsize::new(0) }; MAX_FRAMES];
+static SIGNAL_LEN: AtomicUsize = AtomicUsize::new(0);
+/// Sequential number of the last capture requested by the detector thread.
+static SIGNAL_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+/// The capture the handler should do, or 0 if there is none. The handler claims it by resetting it to 0,
+/// so a handler that runs after the detector gave up on its request leaves the frames alone.
+static SIGNAL_PENDING: AtomicUsize = AtomicUsize::new(0);
+/// The last capture done by the handler.
+static SIGNAL_DONE: AtomicUsize = AtomicUsize::new(0);
+/// Stack of the thread the pending capture is requested for.
+static SIGNAL_STACK_START: AtomicUsize = AtomicUsize::new(0);
+static SIGNAL_STACK_END: AtomicUsize = AtomicUsize::new(0);
+
+/// The handler that was installed before the one of the detector. It still receives the signals
+/// that are not sent by the detector.
+static PREVIOUS_ACTION: OnceLock<libc::sigaction> = OnceLock::new();
+
+static WATCHES: Mutex<Vec<Arc<Watch>>> = Mutex::new(Vec::new());
+static DETECTOR: OnceLock<Option<Thread>> = OnceLock::new();
+
+/// A single poll watched by the detector thread.
+struct Watch {
+    thread: libc::pthread_t,
+    stack: Range<usize>,
+    started: Instant,
+    threshold: Duration,
+    finished: AtomicBool,
+    /// Locked for the whole capture, so that the poll can't finish without its result.
+    captured: Mutex<Option<Vec<usize>>>,
+}
+
+/// Stops watching the poll when dropped.
+pub(crate) struct WatchGuard {
+    watch: Option<Arc<Watch>>,
+}
+
+/// Start watching the poll that is about to run on the current thread.
+/// If it runs longer than `threshold`, the stack of the thread is captured while it is still running.
+pub(crate) fn watch(threshold: Duration) -> WatchGuard {
+    let Some(stack) = STACK.with(Clone::clone) else {
+        return WatchGuard { watch: None };
+    };
+    let Some(detector) = DETECTOR.get_or_init(start_detector) else {
+        return WatchGuard { watch: None };
+    };
+    let watch = Arc::new(Watch {
+        // SAFETY: `pthread_self` is always safe to call
+        thread: unsafe { libc::pthread_self() },
+        stack,
+        started: Instant::now(),
+        threshold,
+        finished: AtomicBool::new(false),
+        captured: Mutex::new(None),
+    });
+    lock(&WATCHES).push(watch.clone());
+    detector.unpark();
+    WatchGuard { watch: Some(watch) }
+}
+
+impl WatchGuard {
+    /// Stop watching the poll. Returns the stack of the thread if the poll was blocked for longer than the threshold.
+    pub(crate) fn finish(mut self) -> Option<BlockedPoll> {
+        let watch = self.watch.take()?;
+        let duration = watch.started.elapsed();
+        stop(&watch);
+        let ips = lock(&watch.captured).take()?;
+        Some(BlockedPoll {
+            duration,
+            stack_trace: StackTrace::from_signal(ips),
+        })
+    }
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        // The poll panicked
+        if let Some(watch) = &self.watch {
+            stop(watch);
+        }
+    }
+}
+
+fn stop(watch: &Arc<Watch>) {
+    watch.finished.store(true, Ordering::Release);
+    lock(&WATCHES).retain(|other| !Arc::ptr_eq(other, watch));
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Installs the signal handler and starts the detector thread.
+/// Returns `None` if either fails, then blocked polls are not detected.
+fn start_detector() -> Option<Thread> {
+    // SAFETY: `sigaction` is a plain C struct, for which all zeroes is a valid value
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = on_signal as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void)
+        as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART | libc::SA_SIGINFO;
+    // SAFETY: `sa_mask` is a valid pointer
+    unsafe { libc::sigemptyset(&raw mut action.sa_mask) };
+    // SAFETY: all zeroes is a valid value, see above
+    let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+    // SAFETY: `previous` is a valid pointer, the current action is only read
+    if unsafe { libc::sigaction(SIGNAL, std::ptr::null(), &raw mut previous) } != 0 {
+        return None;
+    }
+    // Stored before the handler is installed, as the handler reads it
+    let _ = PREVIOUS_ACTION.set(previous);
+    // SAFETY: `on_signal` has the signature expected for a handler with `SA_SIGINFO`.
+    // It only uses atomics and reads the stack of the interrupted thread, which are async-signal-safe,
+    // and otherwise calls the previous handler.
+    if unsafe { libc::sigaction(SIGNAL, &raw const action, std::ptr::null_mut()) } != 0 {
+        return None;
+    }
+    thread::Builder::new()
+        .name("timeout-tracing-blocking-detector".to_owned())
+        .spawn(run_detector)
+        .ok()
+        .map(|handle| handle.thread().clone())
+}
+
+fn run_detector() {
+    loop {
+        let now = Instant::now();
+        let mut next_deadline = None;
+        let mut blocked = Vec::new();
+        for watch in lock(&WATCHES).iter() {
+            if lock(&watch.captured).is_some() {
+                continue;
+            }
+            let deadline = watch.started + watch.threshold;
+            if deadline <= now {
+                blocked.push(watch.clone());
+            } else if next_deadline.is_none_or(|next| deadline < next) {
+                next_deadline = Some(deadline);
+            }
+        }
+        for watch in blocked {
+            capture(&watch);
+        }
+        match next_deadline {
+            Some(deadline) => {
+                thread::park_timeout(deadline.saturating_duration_since(Instant::now()));
+            }
+            None => thread::park(),
+        }
+    }
+}
+
+/// Capture the stack of the thread of `watch` by sending it the signal.
+fn capture(watch: &Watch) {
+    let mut captured = lock(&watch.captured);
+    if watch.finished.load(Ordering::Acquire) {
+        return;
+    }
+    let request = SIGNAL_REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+    SIGNAL_STACK_START.store(watch.stack.start, Ordering::Relaxed);
+    SIGNAL_STACK_END.store(watch.stack.end, Ordering::Relaxed);
+    SIGNAL_PENDING.store(request, Ordering::Release);
+    // SAFETY: the thread is alive, as it has not finished the poll yet
+    if unsafe { libc::pthread_kill(watch.thread, SIGNAL) } != 0 {
+        SIGNAL_PENDING.store(0, Ordering::Release);
+        return;
+    }
+    let waiting_since = Instant::now();
+    while SIGNAL_DONE.load(Ordering::Acquire) != request {
+        // Once the handler has claimed the request, it is about to finish, so it is waited for regardless of the timeout
+        if waiting_since.elapsed() > SIGNAL_TIMEOUT
+            && SIGNAL_PENDING
+                .compare_exchange(request, 0, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            return;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    let len = SIGNAL_LEN.load(Ordering::Acquire);
+    *captured = Some(
+        SIGNAL_FRAMES[..len]
+            .iter()
+            .map(|ip| ip.load(Ordering::Relaxed))
+            .collect(),
+    );
+}
+
+/// Walks the stack of the interrupted thread by following its frame pointers.
+/// Unwinding with the debug info, as [`backtrace::trace`] does, is not async-signal-safe: it may lock
+/// and allocate, and deadlock if the thread was interrupted while doing the same.
+/// Frames compiled without frame pointers are skipped, so the stack is most complete
+/// with `-C force-frame-pointers=yes`.
+extern "C" fn on_signal(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    // SAFETY: the kernel passes the details of the signal to handlers installed with `SA_SIGINFO`
+    if !unsafe { sent_by_detector(info) } {
+        // SAFETY: the arguments are the ones this handler was called with
+        unsafe { call_previous_handler(signal, info, context) };
+        return;
+    }
+    let request = SIGNAL_PENDING.load(Ordering::Acquire);
+    if request == 0 {
+        return;
+    }
+    let stack =
+        SIGNAL_STACK_START.load(Ordering::Relaxed)..SIGNAL_STACK_END.load(Ordering::Relaxed);
+    // SAFETY: the kernel passes the context of the interrupted thread to handlers installed with `SA_SIGINFO`
+    let Some(Registers { ip, sp, mut fp }) = (unsafe { registers(context) }) else {
+        return;
+    };
+    // The signal may also be sent to other threads, e.g. by the kernel for out-of-band socket data
+    if !stack.contains(&sp)
+        || SIGNAL_PENDING
+            .compare_exchange(request, 0, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    SIGNAL_FRAMES[0].store(ip, Ordering::Relaxed);
+    let mut len = 1;
+    // Each frame starts with the frame pointer of its caller, followed by the return address.
+    // The frames are above the stack pointer and grow towards the end of the stack, anything else
+    // means that the register holds something else than a frame pointer.
+    let mut lowest = sp;
+    while len < MAX_FRAMES
+        && fp >= lowest
+        && fp.saturating_add(2 * mem::size_of::<usize>()) <= stack.end
+        && fp % mem::align_of::<usize>() == 0
+    {
+        let frame = fp as *const usize;
+        // SAFETY: the frame is within the stack of the interrupted thread, which is mapped
+        let caller_fp = unsafe { frame.read() };
+        // SAFETY: same as above, the return address is checked to be within the stack too
+        let return_address = unsafe { frame.wrapping_add(1).read() };
+        if return_address == 0 {
+            break;
+        }
+        SIGNAL_FRAMES[len].store(return_address, Ordering::Relaxed);
+        len += 1;
+        lowest = fp + 1;
+        fp = caller_fp;
+    }
+    SIGNAL_LEN.store(len, Ordering::Release);
+    SIGNAL_DONE.store(request, Ordering::Release);
+}
+
+/// Returns whether the signal was sent with `pthread_kill` from within this process,
+/// as opposed to e.g. by the kernel for out-of-band socket data.
+///
+/// # Safety
+///
+/// `info` must be the `siginfo_t` passed to a handler installed with `SA_SIGINFO`.
+#[cfg(target_os = "linux")]
+unsafe fn sent_by_detector(info: *mut libc::siginfo_t) -> bool {
+    // SAFETY: guaranteed by the caller
+    let info = unsafe { &*info };
+    if info.si_code != libc::SI_TKILL {
+        return false;
+    }
+    // SAFETY: `si_pid` is set for signals sent with `SI_TKILL`
+    let sender = unsafe { info.si_pid() };
+    // SAFETY: `getpid` is async-signal-safe
+    sender == unsafe { libc::getpid() }
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn sent_by_detector(_info: *mut libc::siginfo_t) -> bool {
+    true
+}
+
+/// Passes a signal that was not sent by the detector to the handler installed before it.
+///
+/// # Safety
+///
+/// The arguments must be the ones passed to a handler installed with `SA_SIGINFO`.
+unsafe fn call_previous_handler(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut c_void,
+) {
+    let Some(previous) = PREVIOUS_ACTION.get() else {
+        return;
+    };
+    // The default action of the signal is to ignore it
+    if previous.sa_sigaction == libc::SIG_DFL || previous.sa_sigaction == libc::SIG_IGN {
+        return;
+    }
+    if previous.sa_flags & libc::SA_SIGINFO == 0 {
+        // SAFETY: handlers installed without `SA_SIGINFO` take the signal number only
+        let handler: extern "C" fn(libc::c_int) = unsafe { mem::transmute(previous.sa_sigaction) };
+        handler(signal);
+    } else {
+        // SAFETY: handlers installed with `SA_SIGINFO` take the same arguments as this one
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void) =
+            unsafe { mem::transmute(previous.sa_sigaction) };
+        handler(signal, info, context);
+    }
+}
+
+/// Registers of the interrupted thread.
+struct Registers {
+    ip: usize,
+    sp: usize,
+    fp: usize,
+}
+
+/// Reads the registers of the interrupted thread from the context passed to the signal handler.
+///
+/// # Safety
+///
+/// `context` must be the `ucontext_t` passed to a handler installed with `SA_SIGINFO`.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[allow(
+    clippy::cast_sign_loss,
+    clippy::cast_possible_truncation,
+    clippy::unnecessary_wraps,
+    reason = "the registers are stored as 64 bit signed integers, and can't be read on other targets"
+)]
+unsafe fn registers(context: *mut c_void) -> Option<Registers> {
+    // SAFETY: guaranteed by the caller
+    let registers = unsafe { &(*context.cast::<libc::ucontext_t>()).uc_mcontext.gregs };
+    Some(Registers {
+        ip: registers[libc::REG_RIP as usize] as usize,
+        sp: registers[libc::REG_RSP as usize] as usize,
+        fp: registers[libc::REG_RBP as usize] as usize,
+    })
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::unnecessary_wraps,
+    reason = "the registers are 64 bit wide, as is `usize`, and can't be read on other targets"
+)]
+unsafe fn registers(context: *mut c_void) -> Option<Registers> {
+    // SAFETY: guaranteed by the caller
+    let registers = unsafe { &(*context.cast::<libc::ucontext_t>()).uc_mcontext };
+    Some(Registers {
+        ip: registers.pc as usize,
+        sp: registers.sp as usize,
+        fp: registers.regs[29] as usize,
+    })
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+unsafe fn registers(_context: *mut c_void) -> Option<Registers> {
+    None
+}
+
+thread_local! {
+    /// Bounds of the stack of the current thread, or `None` if its stack can't be captured.
+    static STACK: Option<Range<usize>> = stack_bounds();
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn stack_bounds() -> Option<Range<usize>> {
+    let mut attr = mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+    let mut start = std::ptr::null_mut();
+    let mut size = 0;
+    // SAFETY: `pthread_self` is always safe to call
+    let thread = unsafe { libc::pthread_self() };
+    // SAFETY: `attr` is a valid pointer
+    if unsafe { libc::pthread_getattr_np(thread, attr.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: `attr` is initialized by `pthread_getattr_np` and the other pointers are valid
+    let result =
+        unsafe { libc::pthread_attr_getstack(attr.as_ptr(), &raw mut start, &raw mut size) };
+    // SAFETY: `attr` is initialized and is not used afterwards
+    unsafe { libc::pthread_attr_destroy(attr.as_mut_ptr()) };
+    (result == 0).then(|| start as usize..start as usize + size)
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn stack_bounds() -> Option<Range<usize>> {
+    None
+}
+
+/// Resolves the instruction pointers captured by the signal handler and removes the frames
+/// of the timeout that ran the poll.
+pub(crate) fn resolve(ips: &[usize]) -> Vec<StackFrame> {
+    let mut frames = Vec::new();
+    for (idx, &ip) in ips.iter().enumerate() {
+        // All frames except the interrupted one point to the instruction after the call
+        let addr = if idx == 0 { ip } else { ip.saturating_sub(1) };
+        backtrace::resolve(addr as *mut c_void, |symbol| {
+            frames.push(StackFrame {
+                function: symbol.name().map(|name| format!("{name:#}")),
+                file: symbol.filename().map(ToOwned::to_owned),
+                line: symbol.lineno(),
+                column: symbol.colno(),
+            });
+        });
+    }
+    if let Some(outer) = frames
+        .iter()
+        .position(|frame| function(frame).starts_with("timeout_tracing::stats::PollRecorder"))
+    {
+        frames.truncate(outer);
+    }
+    while frames
+        .last()
+        .is_some_and(|frame| function(frame).starts_with("<timeout_tracing::TimeoutFuture"))
+    {
+        frames.pop();
+    }
+    frames
+}
@@ -0,0 +1,107 @@
+use std::{fmt::Display, sync::Arc, time::Duration};
+
+use crate::{TimeoutFuture, trace::CaptureSpanTrace};
+
+/// A builder of [`TimeoutFuture`]s that identify the operation they limit with a label and context fields.
+/// The label and fields are attached to the `deadline` span of the timeout, reported in [`crate::TimeoutElapsed`],
+/// shown in its `Display` output and serialized with it, so that reports from many timeouts can be told apart.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::Timeout;
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let user_id = 42;
+/// let result = Timeout::new(Duration::from_millis(10))
+///     .label("fetch_user")
+///     .field("user_id", user_id)
+///     .run(sleep(Duration::from_secs(1)))
+///     .await;
+/// let elapsed = result.err().unwrap();
+/// assert!(elapsed.to_string().starts_with("timeout fetch_user{user_id=42} elapsed"));
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct Timeout<C = CaptureSpanTrace> {
+    duration: Duration,
+    capture: C,
+    label: Option<Arc<str>>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Timeout {
+    /// Create a timeout of `duration` that captures span traces with [`CaptureSpanTrace`].
+    #[must_use]
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            capture: CaptureSpanTrace,
+            label: None,
+            fields: Vec::new(),
+        }
+    }
+}
+
+impl<C> Timeout<C> {
+    /// Choose which kind of trace should be captured.
+    #[must_use]
+    pub fn capture<C2>(self, capture: C2) -> Timeout<C2> {
+        Timeout {
+            duration: self.duration,
+            capture,
+            label: self.label,
+            fields: self.fields,
+        }
+    }
+
+    /// Label the timeout, e.g. with the name of the operation it limits.
+    #[must_use]
+    pub fn label(mut self, label: impl Into<Arc<str>>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Add a context field, e.g. the id of the request the operation belongs to.
+    #[must_use]
+    pub fn field(mut self, name: &'static str, value: impl Display) -> Self {
+        self.fields.push((name, value.to_string()));
+        self
+    }
+
+    /// Drive the future `fut` to completion, while limiting its run time. See [`crate::timeout`].
+    pub fn run<Fut>(self, fut: Fut) -> TimeoutFuture<C, Fut> {
+        let mut timeout = TimeoutFuture::new(self.duration, self.capture, fut);
+        timeout.label = self.label;
+        timeout.fields = self.fields;
+        timeout
+    }
+}
+
+/// Formats context fields the same way as `tracing` formats span fields: `name=value`, separated by spaces.
+pub(crate) struct DisplayFields<'a>(pub(crate) &'a [(&'static str, String)]);
+
+impl Display for DisplayFields<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, (name, value)) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{name}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes context fields as a map from their names to their values.
+#[cfg(feature = "serde")]
+pub(crate) struct SerializeFields<'a>(pub(crate) &'a [(&'static str, String)]);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SerializeFields<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    pin::Pin,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use pin_project_lite::pin_project;
+
+use crate::{TimeoutElapsed, TimeoutFuture, timer::Timer, trace::CaptureTrace};
+
+/// A token that tells a future to shut down cooperatively, e.g. to flush buffers or to roll back a transaction.
+/// Cancelled by [`GracefulTimeoutFuture`] when its deadline elapses.
+///
+/// Clones share the same state. The token does not depend on an async runtime.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    /// Wakers of the [`Cancelled`] futures waiting for the token, each one in the slot of its future.
+    /// Slots are freed when the futures are dropped and reused by new ones.
+    wakers: Mutex<Vec<Option<Waker>>>,
+}
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token and wake all futures waiting in [`CancellationToken::cancelled`].
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+        for waker in self.inner.wakers().iter_mut().filter_map(Option::take) {
+            waker.wake();
+        }
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Returns a future that completes when the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled {
+            token: self,
+            slot: None,
+        }
+    }
+}
+
+impl TokenState {
+    fn wakers(&self) -> MutexGuard<'_, Vec<Option<Waker>>> {
+        self.wakers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+    /// Slot of the waker of this future in the token, once it has been polled
+    slot: Option<usize>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let token = self.token;
+        let mut wakers = token.inner.wakers();
+        if let Some(slot) = self.slot {
+            // The slot is only removed after the token is cancelled, which is checked again below
+            if let Some(waker) = wakers.get_mut(slot)
+                && !waker
+                    .as_ref()
+                    .is_some_and(|waker| waker.will_wake(cx.waker()))
+            {
+                *waker = Some(cx.waker().clone());
+            }
+        } else {
+            let slot = wakers.iter().position(Option::is_none).unwrap_or_else(|| {
+                wakers.push(None);
+                wakers.len() - 1
+            });
+            wakers[slot] = Some(cx.waker().clone());
+            self.slot = Some(slot);
+        }
+        drop(wakers);
+        // The token may have been cancelled before the waker was stored
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        let Some(slot) = self.slot else {
+            return;
+        };
+        let mut wakers = self.token.inner.wakers();
+        if let Some(waker) = wakers.get_mut(slot) {
+            *waker = None;
+        }
+        while wakers.last().is_some_and(Option::is_none) {
+            wakers.pop();
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`TimeoutFuture::grace_period`].
+    pub struct GracefulTimeoutFuture<C: CaptureTrace, Fut, T: Timer> {
+        #[pin]
+        timeout: TimeoutFuture<C, Fut, T>,
+        capture: Option<Arc<C>>,
+        token: CancellationToken,
+        grace: Duration,
+        at_deadline: Option<TimeoutElapsed<C::Trace>>,
+    }
+}
+
+impl<C, Fut, T> GracefulTimeoutFuture<C, Fut, T>
+where
+    C: CaptureTrace,
+    T: Timer,
+{
+    pub(crate) fn new(
+        timeout: TimeoutFuture<C, Fut, T>,
+        token: CancellationToken,
+        grace: Duration,
+    ) -> Self {
+        Self {
+            capture: timeout.capture.clone(),
+            timeout,
+            token,
+            grace,
+            at_deadline: None,
+        }
+    }
+}
+
+impl<C, Fut, T> Future for GracefulTimeoutFuture<C, Fut, T>
+where
+    C: CaptureTrace + Send + 'static,
+    Fut: Future,
+    T: Timer,
+{
+    type Output = Result<Fut::Output, TimeoutElapsed<C::Trace>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if this.at_deadline.is_none() {
+            match this.timeout.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(result)) => return Poll::Ready(Ok(result)),
+                Poll::Ready(Err(elapsed)) => {
+                    *this.at_deadline = Some(elapsed);
+                    this.token.cancel();
+                    // The timeout keeps the inner future after it elapsed,
+                    // so it is rearmed to give it the grace period
+                    if let Some(capture) = this.capture.take() {
+                        this.timeout.as_mut().rearm(capture, T::now() + *this.grace);
+                    }
+                }
+            }
+        }
+        let stuck = match this.timeout.poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            // The output of a cancelled future is discarded, the timeout has elapsed anyway
+            Poll::Ready(Ok(_)) => None,
+            Poll::Ready(Err(elapsed)) => Some(elapsed.active_traces),
+        };
+        let mut at_deadline = this.at_deadline.take().expect("the deadline has elapsed");
+        at_deadline.stuck_during_cancellation = stuck;
+        Poll::Ready(Err(at_deadline))
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::timer::Timer;
+
+/// The time limit of a [`crate::TimeoutFuture`] within the chain of nested timeouts, see [`crate::TimeoutElapsed::deadlines`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeadlineInfo {
+    /// The label set with [`crate::TimeoutFuture::label`].
+    pub label: Option<Arc<str>>,
+    /// The time limit of the timeout.
+    pub duration: Duration,
+    /// The budget left when the timeout that reported it elapsed, zero for the elapsed timeout itself.
+    pub remaining: Duration,
+}
+
+impl Display for DeadlineInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(label) = &self.label {
+            write!(f, "{label}: ")?;
+        }
+        write!(
+            f,
+            "{:?} timeout, {:?} remaining",
+            self.duration, self.remaining
+        )
+    }
+}
+
+/// The limit that a [`crate::TimeoutFuture`] enforced when it elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Limit {
+    /// The duration or deadline the timeout was created with.
+    Local,
+    /// The tighter deadline inherited from an enclosing timeout, see [`crate::TimeoutFuture::propagate_deadline`].
+    Inherited,
+}
+
+thread_local! {
+    /// Timeouts that are polling their inner futures on this thread, the innermost one last.
+    static DEADLINES: RefCell<Vec<DeadlineInfo>> = const { RefCell::new(Vec::new()) };
+    /// The tightest deadline propagated by the timeouts that are polling their inner futures on this thread.
+    static CURRENT_DEADLINE: Cell<Option<PropagatedDeadline>> = const { Cell::new(None) };
+}
+
+/// A deadline propagated by a timeout.
+#[derive(Debug, Clone, Copy)]
+struct PropagatedDeadline {
+    /// Points to the `T::Instant` of the deadline, which outlives the [`CurrentDeadlineScope`] that propagates it.
+    deadline: *const (),
+    /// Returns the time left until `deadline`. Timers may use different clocks, e.g. a paused Tokio clock,
+    /// so it is measured with the clock of the timer of the timeout.
+    remaining: unsafe fn(*const ()) -> Duration,
+    /// Changes whenever the deadline changes, so that nested timeouts only follow it when it does.
+    version: u64,
+}
+
+impl PropagatedDeadline {
+    fn current(self) -> CurrentDeadline {
+        CurrentDeadline {
+            // SAFETY: the deadline is only accessible while the scope that propagates it is active
+            remaining: unsafe { (self.remaining)(self.deadline) },
+            version: self.version,
+        }
+    }
+}
+
+/// # Safety
+///
+/// `deadline` must point to a `T::Instant`.
+unsafe fn remaining_until<T: Timer>(deadline: *const ()) -> Duration {
+    // SAFETY: guaranteed by the caller
+    let deadline = unsafe { *deadline.cast::<T::Instant>() };
+    deadline - T::now()
+}
+
+/// The tightest of the deadlines propagated on this thread, see [`current_deadline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CurrentDeadline {
+    pub(crate) remaining: Duration,
+    pub(crate) version: u64,
+}
+
+/// Returns a new version for a deadline that has changed, see [`CurrentDeadline::version`].
+pub(crate) fn next_version() -> u64 {
+    static VERSIONS: AtomicU64 = AtomicU64::new(0);
+    VERSIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Returns the time left until the current deadline propagated by an enclosing timeout,
+/// e.g. to size the deadlines of downstream requests. Returns `None` outside of the timeouts
+/// created with [`crate::TimeoutFuture::propagate_deadline`].
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use timeout_tracing::{CaptureSpanTrace, remaining, timeout};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let result = timeout(Duration::from_secs(1), CaptureSpanTrace, async { remaining() })
+///     .propagate_deadline()
+///     .await;
+/// assert!(result.unwrap().unwrap() <= Duration::from_secs(1));
+/// assert_eq!(remaining(), None);
+/// # });
+/// ```
+#[must_use]
+pub fn remaining() -> Option<Duration> {
+    current_deadline().map(|deadline| deadline.remaining)
+}
+
+/// Returns the tightest deadline propagated by the timeouts that are polling their inner futures on this thread.
+pub(crate) fn current_deadline() -> Option<CurrentDeadline> {
+    CURRENT_DEADLINE.get().map(PropagatedDeadline::current)
+}
+
+/// Propagates the deadline to the code polled within the scope, unless an enclosing deadline is tighter.
+pub(crate) struct CurrentDeadlineScope<'a> {
+    previous: Option<PropagatedDeadline>,
+    /// The propagated deadline is borrowed for as long as it is current
+    _deadline: PhantomData<&'a ()>,
+}
+
+impl<'a> CurrentDeadlineScope<'a> {
+    pub(crate) fn enter<T: Timer>(deadline: &'a T::Instant, version: u64) -> Self {
+        let propagated = PropagatedDeadline {
+            deadline: std::ptr::from_ref(deadline).cast(),
+            remaining: remaining_until::<T>,
+            version,
+        };
+        let previous = CURRENT_DEADLINE.get();
+        // Neither deadline changes while the scope is active, so the tighter one stays tighter
+        let tightest = match previous {
+            Some(previous) if previous.current().remaining <= propagated.current().remaining => {
+                previous
+            }
+            _ => propagated,
+        };
+        CURRENT_DEADLINE.set(Some(tightest));
+        Self {
+            previous,
+            _deadline: PhantomData,
+        }
+    }
+}
+
+impl Drop for CurrentDeadlineScope<'_> {
+    fn drop(&mut self) {
+        CURRENT_DEADLINE.set(self.previous);
+    }
+}
+
+/// Returns the timeouts that are polling their inner futures on this thread, the outermost one first.
+pub(crate) fn enclosing_deadlines() -> Vec<DeadlineInfo> {
+    DEADLINES.with_borrow(Clone::clone)
+}
+
+/// Marks the timeout as enclosing the code polled within the scope.
+pub(crate) struct DeadlineScope;
+
+impl DeadlineScope {
+    pub(crate) fn enter(deadline: DeadlineInfo) -> Self {
+        DEADLINES.with_borrow_mut(|deadlines| deadlines.push(deadline));
+        Self
+    }
+}
+
+impl Drop for DeadlineScope {
+    fn drop(&mut self) {
+        DEADLINES.with_borrow_mut(Vec::pop);
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use crate::{
+    ForwardCapture, TimeoutFuture, forward_capture, timeout, timeout_at,
+    timer::{DefaultTimer, Timer},
+    trace::CaptureSpanTrace,
+};
+
+/// An extension trait for [`Future`]s that provides method-chaining versions of [`timeout`] and [`timeout_at`].
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, TimeoutTracingExt};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let result = sleep(Duration::from_secs(1))
+///     .timeout_traced(Duration::from_millis(10), CaptureSpanTrace)
+///     .await;
+/// assert!(result.is_err());
+/// # });
+/// ```
+pub trait TimeoutTracingExt: Future + Sized {
+    /// Limit the run time of this future to `duration`.
+    /// See [`timeout`].
+    fn timeout_traced<C>(self, duration: Duration, capture: C) -> TimeoutFuture<C, Self> {
+        timeout(duration, capture, self)
+    }
+
+    /// Limit the run time of this future to the absolute `deadline`.
+    /// See [`timeout_at`].
+    fn timeout_traced_at<C>(
+        self,
+        deadline: <DefaultTimer as Timer>::Instant,
+        capture: C,
+    ) -> TimeoutFuture<C, Self> {
+        timeout_at(deadline, capture, self)
+    }
+
+    /// Limit the run time of this future to `duration`, capturing span traces with [`CaptureSpanTrace`].
+    /// See [`timeout`].
+    fn timeout_traced_default(self, duration: Duration) -> TimeoutFuture<CaptureSpanTrace, Self> {
+        timeout(duration, CaptureSpanTrace, self)
+    }
+
+    /// Capture the await points of this future even when it is polled by a combinator with its own waker.
+    /// See [`forward_capture`].
+    fn forward_capture(self) -> ForwardCapture<Self> {
+        forward_capture(self)
+    }
+}
+
+impl<Fut: Future> TimeoutTracingExt for Fut {}
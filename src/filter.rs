@@ -0,0 +1,190 @@
+use std::path::{Component, Path};
+
+use crate::stack::StackFrame;
+
+/// Selects which frames of a captured [`crate::StackTrace`] are kept.
+///
+/// By default:
+/// - frames of the trace capture itself (this crate's waker, `Waker::clone`, tokio's `AtomicWaker`,
+///   `catch_unwind`) are removed from the top of the stack, and the poll bookkeeping frames of nested timeouts
+///   are removed from the middle of it;
+/// - frames above the [`crate::TimeoutFuture`] or [`crate::WatchdogFuture`] that captured the trace
+///   (the caller and the runtime's `block_on`) are removed from the bottom of the stack.
+///
+/// Additionally, frames can be restricted to an allow list of crates or have a deny list of crates removed,
+/// and frames of dependencies and the standard library can be removed with [`FrameFilter::user_code_only`].
+///
+/// # Examples
+/// ```rust
+/// # use timeout_tracing::{CaptureSpanAndStackTrace, FrameFilter};
+/// let capture = CaptureSpanAndStackTrace.with_filter(
+///     FrameFilter::new()
+///         .deny_crate("tokio")
+///         .deny_crate("tracing"),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameFilter {
+    trim_capture_frames: bool,
+    trim_outer_frames: bool,
+    user_code_only: bool,
+    allow_crates: Vec<String>,
+    deny_crates: Vec<String>,
+}
+
+impl Default for FrameFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameFilter {
+    /// Filter that trims the capture frames and the outer frames.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            trim_capture_frames: true,
+            trim_outer_frames: true,
+            user_code_only: false,
+            allow_crates: Vec::new(),
+            deny_crates: Vec::new(),
+        }
+    }
+
+    /// Filter that keeps all frames.
+    #[must_use]
+    pub const fn keep_all() -> Self {
+        Self {
+            trim_capture_frames: false,
+            trim_outer_frames: false,
+            user_code_only: false,
+            allow_crates: Vec::new(),
+            deny_crates: Vec::new(),
+        }
+    }
+
+    /// Whether to remove the frames of the trace capture from the top of the stack
+    /// and the poll bookkeeping frames of nested timeouts.
+    #[must_use]
+    pub fn trim_capture_frames(self, trim: bool) -> Self {
+        Self {
+            trim_capture_frames: trim,
+            ..self
+        }
+    }
+
+    /// Whether to remove the frames above the future that captured the trace from the bottom of the stack.
+    #[must_use]
+    pub fn trim_outer_frames(self, trim: bool) -> Self {
+        Self {
+            trim_outer_frames: trim,
+            ..self
+        }
+    }
+
+    /// Whether to keep only frames of the code being built: frames of crates from the cargo registry
+    /// or git dependencies, frames of the standard library and frames without a source file are removed.
+    #[must_use]
+    pub fn user_code_only(self, user_code_only: bool) -> Self {
+        Self {
+            user_code_only,
+            ..self
+        }
+    }
+
+    /// Keep only frames of the given crates. Can be called multiple times.
+    /// Crate names are matched against the first path segment of the function name,
+    /// e.g. `my_app` for `my_app::handler::{{closure}}`.
+    #[must_use]
+    pub fn allow_crate(mut self, name: impl Into<String>) -> Self {
+        self.allow_crates.push(name.into());
+        self
+    }
+
+    /// Remove frames of the given crate. Can be called multiple times.
+    #[must_use]
+    pub fn deny_crate(mut self, name: impl Into<String>) -> Self {
+        self.deny_crates.push(name.into());
+        self
+    }
+
+    pub(crate) fn apply(&self, mut frames: Vec<StackFrame>) -> Vec<StackFrame> {
+        // The innermost frame of the future that captured the trace
+        let outer = frames.iter().position(|frame| {
+            function(frame).starts_with("timeout_tracing::waker::poll_with_capture")
+        });
+        if self.trim_outer_frames
+            && let Some(outer) = outer
+        {
+            frames.truncate(outer);
+        }
+        if self.trim_capture_frames {
+            let search_end = outer.unwrap_or(frames.len());
+            if let Some(waker) = frames[..search_end].iter().rposition(|frame| {
+                function(frame).starts_with("timeout_tracing::waker::TracingTimeoutWaker")
+            }) {
+                let leaf = frames[waker + 1..]
+                    .iter()
+                    .position(|frame| !is_waker_registration(function(frame)))
+                    .map_or(frames.len(), |idx| waker + 1 + idx);
+                frames.drain(..leaf);
+            }
+            frames.retain(|frame| !function(frame).starts_with("timeout_tracing::stats::"));
+        }
+        if self.user_code_only {
+            frames.retain(|frame| frame.file.as_deref().is_some_and(is_user_file));
+        }
+        if !self.allow_crates.is_empty() || !self.deny_crates.is_empty() {
+            frames.retain(|frame| {
+                let name = crate_name(function(frame));
+                (self.allow_crates.is_empty() || self.allow_crates.iter().any(|c| c == name))
+                    && !self.deny_crates.iter().any(|c| c == name)
+            });
+        }
+        frames
+    }
+}
+
+pub(crate) fn function(frame: &StackFrame) -> &str {
+    frame.function.as_deref().unwrap_or_default()
+}
+
+/// Frames between `Waker::clone` and the leaf future that stores the waker.
+fn is_waker_registration(function: &str) -> bool {
+    matches!(
+        crate_name(function),
+        "core" | "std" | "alloc" | "__rust_try"
+    ) || function.starts_with("tokio::sync::task::atomic_waker::")
+        || function.starts_with("futures_core::task::__internal::atomic_waker::")
+}
+
+/// Returns `false` for sources of the standard library (`/rustc/<hash>/library/...` or `rustlib/src/rust/...`)
+/// and of dependencies downloaded by cargo (`registry/src/...` or `git/checkouts/...` within the cargo home).
+fn is_user_file(path: &Path) -> bool {
+    let components: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    if components.first() == Some(&"rustc") {
+        return false;
+    }
+    !components
+        .windows(2)
+        .any(|pair| matches!(pair, ["registry" | "rustlib", "src"] | ["git", "checkouts"]))
+}
+
+/// Returns the crate of a demangled function name,
+/// e.g. `core` for `<&core::task::wake::Waker as tokio::sync::task::atomic_waker::WakerRef>::into_waker`.
+fn crate_name(function: &str) -> &str {
+    let mut function = function.trim_start_matches(['<', '&']);
+    for prefix in ["mut ", "dyn "] {
+        function = function.strip_prefix(prefix).unwrap_or(function);
+    }
+    let end = function
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(function.len());
+    &function[..end]
+}
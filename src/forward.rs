@@ -0,0 +1,232 @@
+use std::{
+    cell::RefCell,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Waker},
+};
+
+use pin_project_lite::pin_project;
+
+use crate::waker::{capturing_waker, is_capturing};
+
+/// Forward the trace capture of an enclosing [`crate::TimeoutFuture`] or [`crate::WatchdogFuture`]
+/// to the future or stream `inner`.
+///
+/// Combinators like [`futures::stream::FuturesUnordered`](https://docs.rs/futures/latest/futures/stream/struct.FuturesUnordered.html),
+/// `FuturesOrdered`, `buffer_unordered` or `StreamMap` poll their children with their own wakers,
+/// so the await points of the children are not captured. Wrap each child with `forward_capture`
+/// to capture them as well. Before capturing the traces, the wrapped children are woken,
+/// so that the combinator polls them even if they are not ready. Outside of trace capture,
+/// the wrapper just polls `inner`.
+///
+/// Tasks spawned with [`tokio::spawn`] are not polled by the combinator, use [`crate::spawn_traced`] for them.
+///
+/// Implementing [`futures_core::Stream`](https://docs.rs/futures-core/latest/futures_core/stream/trait.Stream.html)
+/// requires the `futures` feature.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use futures::{StreamExt, stream::FuturesUnordered};
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, forward_capture, timeout};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let mut children = FuturesUnordered::new();
+/// for _ in 0..3 {
+///     children.push(forward_capture(sleep(Duration::from_secs(1))));
+/// }
+/// let result = timeout(Duration::from_millis(10), CaptureSpanTrace, async {
+///     while let Some(()) = children.next().await {}
+/// })
+/// .await;
+/// // One trace for each child and one for `FuturesUnordered` itself
+/// assert_eq!(result.err().unwrap().active_traces.len(), 4);
+/// # });
+/// ```
+pub fn forward_capture<T>(inner: T) -> ForwardCapture<T> {
+    ForwardCapture {
+        slot: None,
+        unregistered: Some(Unregistered::new()),
+        inner,
+    }
+}
+
+pin_project! {
+    /// Future or stream returned by [`forward_capture`].
+    pub struct ForwardCapture<T> {
+        slot: Option<WakerSlot>,
+        unregistered: Option<Unregistered>,
+        #[pin]
+        inner: T,
+    }
+}
+
+impl<T> ForwardCapture<T> {
+    /// Returns the wrapped future or stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<Fut: Future> Future for ForwardCapture<Fut> {
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Some(waker) = capturing_waker(cx.waker()) {
+            this.inner.poll(&mut Context::from_waker(&waker))
+        } else {
+            register_waker(this.slot, this.unregistered, cx.waker());
+            this.inner.poll(cx)
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<S: futures_core::Stream> futures_core::Stream for ForwardCapture<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Some(waker) = capturing_waker(cx.waker()) {
+            this.inner.poll_next(&mut Context::from_waker(&waker))
+        } else {
+            register_waker(this.slot, this.unregistered, cx.waker());
+            this.inner.poll_next(cx)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// The last waker a [`ForwardCapture`] was polled with.
+type WakerSlot = Arc<Mutex<Option<Waker>>>;
+
+/// Number of [`ForwardCapture`]s that have not registered their [`WakerSlot`] within an enclosing timeout yet.
+/// While there are any, the timeouts enter their [`ForwardScope`] on every poll.
+static UNREGISTERED: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a [`ForwardCapture`] in [`UNREGISTERED`] until it is dropped.
+struct Unregistered {
+    /// Whether the future was woken to register once the enclosing timeouts enter their scope.
+    woken: bool,
+}
+
+impl Unregistered {
+    fn new() -> Self {
+        UNREGISTERED.fetch_add(1, Ordering::Relaxed);
+        Self { woken: false }
+    }
+}
+
+impl Drop for Unregistered {
+    fn drop(&mut self) {
+        UNREGISTERED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Wakers of the futures wrapped with [`forward_capture`] within a [`crate::TimeoutFuture`]
+/// or [`crate::WatchdogFuture`].
+#[derive(Default)]
+pub(crate) struct ForwardedWakers {
+    slots: Mutex<Vec<Weak<Mutex<Option<Waker>>>>>,
+    /// Whether any slot was registered, checked without locking on every poll.
+    registered: AtomicBool,
+}
+
+impl ForwardedWakers {
+    fn register(&self, slot: &WakerSlot) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.retain(|slot| slot.strong_count() > 0);
+        slots.push(Arc::downgrade(slot));
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    /// Wake all forwarded futures, so that their combinators poll them during trace capture.
+    pub(crate) fn wake_all(&self) {
+        let slots: Vec<_> = self
+            .slots
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for slot in slots {
+            if let Some(waker) = &*slot.lock().unwrap() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+thread_local! {
+    static FORWARD_SCOPES: RefCell<Vec<Arc<ForwardedWakers>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Makes the [`ForwardedWakers`] of a [`crate::TimeoutFuture`] or [`crate::WatchdogFuture`] available
+/// to [`ForwardCapture`]s while its inner future is polled.
+pub(crate) struct ForwardScope;
+
+impl ForwardScope {
+    pub(crate) fn enter(wakers: &Arc<ForwardedWakers>) -> Self {
+        FORWARD_SCOPES.with_borrow_mut(|scopes| scopes.push(wakers.clone()));
+        Self
+    }
+
+    /// Enters the scope only if a [`ForwardCapture`] may need it: while traces are being captured,
+    /// after a [`ForwardCapture`] has registered within it or while some [`ForwardCapture`] has not registered yet.
+    pub(crate) fn enter_if_needed(wakers: &Arc<ForwardedWakers>) -> Option<Self> {
+        let needed = is_capturing()
+            || wakers.registered.load(Ordering::Relaxed)
+            || UNREGISTERED.load(Ordering::Relaxed) > 0;
+        needed.then(|| Self::enter(wakers))
+    }
+}
+
+impl Drop for ForwardScope {
+    fn drop(&mut self) {
+        FORWARD_SCOPES.with_borrow_mut(Vec::pop);
+    }
+}
+
+fn register_waker(
+    slot: &mut Option<WakerSlot>,
+    unregistered: &mut Option<Unregistered>,
+    waker: &Waker,
+) {
+    match slot {
+        Some(slot) => {
+            let mut slot = slot.lock().unwrap();
+            if !slot.as_ref().is_some_and(|old| old.will_wake(waker)) {
+                *slot = Some(waker.clone());
+            }
+        }
+        None => FORWARD_SCOPES.with_borrow(|scopes| {
+            if scopes.is_empty() {
+                // The enclosing timeouts may have skipped their scopes because this future was created
+                // within the same poll. They enter them on the next poll, as this future is now counted.
+                if let Some(unregistered) = unregistered
+                    && !unregistered.woken
+                {
+                    unregistered.woken = true;
+                    waker.wake_by_ref();
+                }
+                return;
+            }
+            let new_slot = Arc::new(Mutex::new(Some(waker.clone())));
+            // Register within all enclosing timeouts, as any of them may capture the traces
+            for scope in scopes {
+                scope.register(&new_slot);
+            }
+            *slot = Some(new_slot);
+            *unregistered = None;
+        }),
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    fmt::Display,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::warn;
+
+use crate::{TimeoutElapsed, TimeoutFuture, trace::CaptureTrace};
+
+/// A [`tower_layer::Layer`] that limits the run time of every request of the wrapped service with [`crate::timeout`].
+///
+/// When a request times out, the [`TimeoutElapsed`] is first passed to a [`ReportTimeout`] (nothing by default)
+/// and then converted into the response or the error of the service by a [`HandleTimeout`]
+/// (`S::Error::from(elapsed)` by default).
+///
+/// # Examples
+/// ```rust
+/// # use std::{convert::Infallible, time::Duration};
+/// # use tokio::time::sleep;
+/// # use tower::{ServiceBuilder, ServiceExt, service_fn};
+/// # use timeout_tracing::{CaptureSpanTrace, LogTimeout, TracingTimeoutLayer};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let service = ServiceBuilder::new()
+///     .layer(
+///         TracingTimeoutLayer::new(Duration::from_millis(10), CaptureSpanTrace)
+///             .report_with(LogTimeout)
+///             .on_timeout(|_elapsed| Ok(504)),
+///     )
+///     .service(service_fn(|()| async {
+///         sleep(Duration::from_secs(1)).await;
+///         Ok::<_, Infallible>(200)
+///     }));
+/// assert_eq!(service.oneshot(()).await, Ok(504));
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct TracingTimeoutLayer<C, H = ElapsedIntoError, R = ()> {
+    duration: Duration,
+    capture: C,
+    handler: H,
+    reporter: R,
+}
+
+impl<C> TracingTimeoutLayer<C> {
+    /// Limit the run time of each request to `duration`, capturing the traces with `capture`.
+    pub fn new(duration: Duration, capture: C) -> Self {
+        Self {
+            duration,
+            capture,
+            handler: ElapsedIntoError,
+            reporter: (),
+        }
+    }
+}
+
+impl<C, H, R> TracingTimeoutLayer<C, H, R> {
+    /// Convert [`TimeoutElapsed`] into the response or the error of the service with `handler`.
+    pub fn on_timeout<H2>(self, handler: H2) -> TracingTimeoutLayer<C, H2, R> {
+        TracingTimeoutLayer {
+            duration: self.duration,
+            capture: self.capture,
+            handler,
+            reporter: self.reporter,
+        }
+    }
+
+    /// Pass every [`TimeoutElapsed`] to `reporter` before it is converted by the [`HandleTimeout`].
+    pub fn report_with<R2>(self, reporter: R2) -> TracingTimeoutLayer<C, H, R2> {
+        TracingTimeoutLayer {
+            duration: self.duration,
+            capture: self.capture,
+            handler: self.handler,
+            reporter,
+        }
+    }
+}
+
+impl<S, C, H, R> Layer<S> for TracingTimeoutLayer<C, H, R>
+where
+    C: Clone,
+    H: Clone,
+    R: Clone,
+{
+    type Service = TracingTimeout<S, C, H, R>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TracingTimeout {
+            inner,
+            duration: self.duration,
+            capture: self.capture.clone(),
+            handler: self.handler.clone(),
+            reporter: self.reporter.clone(),
+        }
+    }
+}
+
+/// A [`tower_service::Service`] created by [`TracingTimeoutLayer`].
+#[derive(Debug, Clone)]
+pub struct TracingTimeout<S, C, H = ElapsedIntoError, R = ()> {
+    inner: S,
+    duration: Duration,
+    capture: C,
+    handler: H,
+    reporter: R,
+}
+
+impl<S, Req, C, H, R> Service<Req> for TracingTimeout<S, C, H, R>
+where
+    S: Service<Req>,
+    C: CaptureTrace + Clone + Send + 'static,
+    H: HandleTimeout<C::Trace, S::Response, S::Error> + Clone,
+    R: ReportTimeout<C::Trace> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TracingTimeoutResponseFuture<S::Future, C, H, R>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        TracingTimeoutResponseFuture {
+            inner: TimeoutFuture::new(self.duration, self.capture.clone(), self.inner.call(req)),
+            handler: self.handler.clone(),
+            reporter: self.reporter.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of [`TracingTimeout`].
+    pub struct TracingTimeoutResponseFuture<Fut, C, H, R> {
+        #[pin]
+        inner: TimeoutFuture<C, Fut>,
+        handler: H,
+        reporter: R,
+    }
+}
+
+impl<Fut, C, H, R, Response, Error> Future for TracingTimeoutResponseFuture<Fut, C, H, R>
+where
+    Fut: Future<Output = Result<Response, Error>>,
+    C: CaptureTrace + Send + 'static,
+    H: HandleTimeout<C::Trace, Response, Error>,
+    R: ReportTimeout<C::Trace>,
+{
+    type Output = Result<Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.inner.poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(elapsed)) => {
+                this.reporter.report(&elapsed);
+                Poll::Ready(this.handler.handle_timeout(elapsed))
+            }
+        }
+    }
+}
+
+/// A trait for converting [`TimeoutElapsed`] into the result of a service wrapped with [`TracingTimeoutLayer`].
+/// It is implemented for closures accepting a [`TimeoutElapsed`] and for [`ElapsedIntoError`].
+pub trait HandleTimeout<Trace, Response, Error> {
+    /// Returns the result of the timed out request.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the service, if the timeout should be reported as an error.
+    fn handle_timeout(&self, elapsed: TimeoutElapsed<Trace>) -> Result<Response, Error>;
+}
+
+impl<Trace, Response, Error, F> HandleTimeout<Trace, Response, Error> for F
+where
+    F: Fn(TimeoutElapsed<Trace>) -> Result<Response, Error>,
+{
+    fn handle_timeout(&self, elapsed: TimeoutElapsed<Trace>) -> Result<Response, Error> {
+        self(elapsed)
+    }
+}
+
+/// Implementation of [`HandleTimeout`] that converts [`TimeoutElapsed`] into the error of the service with [`From`],
+/// e.g. into `tower::BoxError`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ElapsedIntoError;
+
+impl<Trace, Response, Error> HandleTimeout<Trace, Response, Error> for ElapsedIntoError
+where
+    Error: From<TimeoutElapsed<Trace>>,
+{
+    fn handle_timeout(&self, elapsed: TimeoutElapsed<Trace>) -> Result<Response, Error> {
+        Err(elapsed.into())
+    }
+}
+
+/// A trait for reporting requests that timed out in a service wrapped with [`TracingTimeoutLayer`].
+/// It is implemented for closures accepting a reference to [`TimeoutElapsed`], for [`LogTimeout`]
+/// and for `()`, which does nothing.
+pub trait ReportTimeout<Trace> {
+    fn report(&self, elapsed: &TimeoutElapsed<Trace>);
+}
+
+impl<Trace> ReportTimeout<Trace> for () {
+    fn report(&self, _elapsed: &TimeoutElapsed<Trace>) {}
+}
+
+impl<Trace, F> ReportTimeout<Trace> for F
+where
+    F: Fn(&TimeoutElapsed<Trace>),
+{
+    fn report(&self, elapsed: &TimeoutElapsed<Trace>) {
+        self(elapsed);
+    }
+}
+
+/// Implementation of [`ReportTimeout`] that emits a `WARN` event via [`tracing`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogTimeout;
+
+impl<Trace: Display> ReportTimeout<Trace> for LogTimeout {
+    fn report(&self, elapsed: &TimeoutElapsed<Trace>) {
+        warn!("request {elapsed}");
+    }
+}
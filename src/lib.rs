@@ -4,6 +4,7 @@ use std::{
     error::Error,
     fmt::Display,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
     time::Duration,
 };
@@ -11,17 +12,92 @@ use std::{
 use pin_project_lite::pin_project;
 use tracing::{Level, span};
 
-use crate::waker::{TracingTimeoutWaker, TracingTimeoutWakerInner};
+use crate::{
+    builder::DisplayFields,
+    deadline::{
+        CurrentDeadlineScope, DeadlineScope, current_deadline, enclosing_deadlines, next_version,
+    },
+    forward::{ForwardScope, ForwardedWakers},
+    on_drop::{DropHook, drop_hook},
+    registry::CaptureNow,
+    stats::PollRecorder,
+    waker::poll_with_capture,
+};
 
 pub use crate::{
+    builder::Timeout, cancel::CancellationToken, cancel::Cancelled, cancel::GracefulTimeoutFuture,
+    deadline::DeadlineInfo, deadline::Limit, deadline::remaining, ext::TimeoutTracingExt,
+    filter::FrameFilter, forward::ForwardCapture, forward::forward_capture,
+    location::AwaitLocations, location::CaptureAwaitLocations, on_drop::DropReport,
+    registry::FutureDump, registry::LogDump, registry::PrintDump, registry::RegisteredFuture,
+    registry::ReportDump, registry::dump_all, registry::dump_all_within, resource::CaptureResource,
+    resource::LabeledResource, resource::ResourceTrace, resource::label_resource,
+    stack::StackFrame, stack::StackTrace, stats::BlockedPoll, stats::PollStats,
+    timer::DefaultTimer, timer::Timer, trace::CaptureFilteredSpanAndStackTrace,
     trace::CaptureSpanAndStackTrace, trace::CaptureSpanTrace, trace::CaptureTrace,
-    trace::StackAndSpanTrace,
+    trace::SpanFrame, trace::StackAndSpanTrace, tree::AsSpanTrace, tree::AwaitNode,
+    tree::AwaitTree, waker::capturing_waker, watchdog::LogStall, watchdog::ReportStall,
+    watchdog::StallReport, watchdog::WatchdogFuture, watchdog::watchdog,
+};
+
+#[cfg(all(feature = "macros", feature = "test-util"))]
+pub use timeout_tracing_macros::test;
+#[cfg(feature = "macros")]
+pub use timeout_tracing_macros::traced_timeout;
+
+#[cfg(feature = "tower")]
+pub use crate::layer::{
+    ElapsedIntoError, HandleTimeout, LogTimeout, ReportTimeout, TracingTimeout,
+    TracingTimeoutLayer, TracingTimeoutResponseFuture,
 };
+#[cfg(all(feature = "signal", unix))]
+pub use crate::signal::dump_on_signal;
+#[cfg(feature = "tokio")]
+pub use crate::spawn::{TracedJoinHandle, spawn_traced};
+#[cfg(feature = "test-util")]
+pub use crate::test_util::run_test;
+#[cfg(feature = "async-io")]
+pub use crate::timer::AsyncIoTimer;
+#[cfg(feature = "futures-timer")]
+pub use crate::timer::FuturesTimerDelay;
+#[cfg(feature = "serde")]
+pub use crate::trace::SerializeTrace;
+#[cfg(feature = "serde")]
+use crate::{builder::SerializeFields, trace::SerializeAsTraces};
+
+// Allows `#[traced_timeout]` to refer to `::timeout_tracing` within this crate
+#[cfg(feature = "macros")]
+extern crate self as timeout_tracing;
 
-#[cfg(test)]
+#[cfg(all(unix, feature = "blocking-detector"))]
+mod blocking;
+mod builder;
+mod cancel;
+mod deadline;
+mod ext;
+mod filter;
+mod forward;
+#[cfg(feature = "tower")]
+mod layer;
+mod location;
+mod on_drop;
+mod registry;
+mod resource;
+#[cfg(all(feature = "signal", unix))]
+mod signal;
+#[cfg(feature = "tokio")]
+mod spawn;
+mod stack;
+mod stats;
+#[cfg(feature = "test-util")]
+mod test_util;
+#[cfg(all(test, feature = "tokio"))]
 mod tests;
+mod timer;
 mod trace;
+mod tree;
 mod waker;
+mod watchdog;
 
 /// Drive the future `fut` to completion, while limiting its run time to `duration`.
 /// If `fut` fails to finish within `furation`, returns span traces for all active
@@ -76,84 +152,543 @@ mod waker;
 /// # });
 /// ```
 pub fn timeout<C, Fut>(duration: Duration, capture: C, fut: Fut) -> TimeoutFuture<C, Fut> {
-    let deadline = tokio::time::sleep(duration);
-    TimeoutFuture {
-        deadline,
-        capture: Some(capture),
-        inner: fut,
-    }
+    TimeoutFuture::new(duration, capture, fut)
+}
+
+/// Drive the future `fut` to completion, while limiting its run time to the absolute `deadline`.
+/// This is the same as [`timeout`], but the time limit is specified as an [`Instant`]
+/// instead of a [`Duration`].
+/// With the default `tokio` feature, the deadline is a [`tokio::time::Instant`].
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::{Instant, sleep};
+/// # use timeout_tracing::{CaptureSpanTrace, timeout_at};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let deadline = Instant::now() + Duration::from_millis(10);
+/// let result = timeout_at(deadline, CaptureSpanTrace, sleep(Duration::from_secs(1))).await;
+/// assert!(result.is_err());
+/// # });
+/// ```
+pub fn timeout_at<C, Fut>(
+    deadline: <DefaultTimer as Timer>::Instant,
+    capture: C,
+    fut: Fut,
+) -> TimeoutFuture<C, Fut> {
+    TimeoutFuture::new_at(deadline, capture, fut)
 }
 
 pin_project! {
-    pub struct TimeoutFuture<C, Fut> {
+    pub struct TimeoutFuture<C, Fut, T = DefaultTimer> {
         #[pin]
-        deadline: tokio::time::Sleep,
-        capture: Option<C>,
+        deadline: T,
+        capture: Option<Arc<C>>,
+        forwarded: Arc<ForwardedWakers>,
+        duration: Duration,
+        label: Option<Arc<str>>,
+        fields: Vec<(&'static str, String)>,
+        propagate: bool,
+        // Version of the deadline propagated by an enclosing timeout that was last applied, see `inherit_deadline`
+        inherited: Option<u64>,
+        // How much later the own deadline is than the inherited deadline the timer is clamped to,
+        // `None` if the timer runs until the own deadline
+        clamped: Option<Duration>,
+        // Version of the deadline of this timeout, see `CurrentDeadline::version`
+        version: u64,
+        stats: PollRecorder,
+        on_drop: Option<DropHook<Fut>>,
         #[pin]
         inner: Fut,
     }
+
+    impl<C, Fut, T> PinnedDrop for TimeoutFuture<C, Fut, T> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if let Some(hook) = this.on_drop.take() {
+                // A future that was never polled has no await points and must not be started now,
+                // and a panic while polling it during unwinding would abort the process
+                let inner = (this.stats.count() > 0 && !std::thread::panicking()).then_some(this.inner);
+                hook(inner, this.forwarded, this.stats.elapsed());
+            }
+        }
+    }
 }
 
-impl<C, Fut> Future for TimeoutFuture<C, Fut>
+impl<C, Fut, T: Timer> TimeoutFuture<C, Fut, T> {
+    /// Same as [`timeout`], but allows to choose the [`Timer`] implementation.
+    pub fn new(duration: Duration, capture: C, fut: Fut) -> Self {
+        Self {
+            deadline: T::sleep(duration),
+            capture: Some(Arc::new(capture)),
+            forwarded: Arc::default(),
+            duration,
+            label: None,
+            fields: Vec::new(),
+            propagate: false,
+            inherited: None,
+            clamped: None,
+            version: next_version(),
+            stats: PollRecorder::new(),
+            on_drop: None,
+            inner: fut,
+        }
+    }
+
+    /// Same as [`timeout_at`], but allows to choose the [`Timer`] implementation.
+    pub fn new_at(deadline: T::Instant, capture: C, fut: Fut) -> Self {
+        Self {
+            deadline: T::sleep_until(deadline),
+            capture: Some(Arc::new(capture)),
+            forwarded: Arc::default(),
+            duration: deadline - T::now(),
+            label: None,
+            fields: Vec::new(),
+            propagate: false,
+            inherited: None,
+            clamped: None,
+            version: next_version(),
+            stats: PollRecorder::new(),
+            on_drop: None,
+            inner: fut,
+        }
+    }
+
+    /// Returns the instant at which the future will time out.
+    pub fn deadline(&self) -> T::Instant {
+        self.deadline.deadline()
+    }
+
+    /// Resets the instant at which the future will time out.
+    /// The new deadline may be either earlier or later than the current one.
+    ///
+    /// This works the same way as `tokio::time::Sleep::reset`.
+    pub fn reset(self: Pin<&mut Self>, deadline: T::Instant) {
+        let this = self.project();
+        this.deadline.reset(deadline);
+        // The new deadline is clamped to the inherited one again on the next poll
+        *this.inherited = None;
+        *this.clamped = None;
+        *this.version = next_version();
+    }
+
+    /// Detect polls of the inner future that block the thread for longer than `threshold`.
+    /// The stack of the blocked thread is captured by a helper thread while the poll is still running,
+    /// and reported in [`TimeoutElapsed::blocked_in_poll`].
+    ///
+    /// The stack is captured by interrupting the thread with `SIGURG`. A handler for it is installed on first use,
+    /// and the handler that was installed before, if any, keeps receiving the `SIGURG` signals not sent by the detector,
+    /// e.g. for out-of-band socket data. Handlers installed afterwards replace the one of the detector and disable it.
+    /// The handler follows the frame pointers of the thread, so frames compiled without them are skipped;
+    /// build with `-C force-frame-pointers=yes` for complete stacks.
+    /// Blocked polls are only detected on Linux on 64-bit x86 and ARM.
+    #[cfg(all(unix, feature = "blocking-detector"))]
+    #[must_use]
+    pub fn detect_blocking(mut self, threshold: Duration) -> Self {
+        self.stats.detect_blocking(threshold);
+        self
+    }
+
+    /// Label the timeout, e.g. with the name of the operation it limits.
+    /// The label is recorded on the `deadline` span of the timeout and reported in [`TimeoutElapsed::label`]
+    /// and [`TimeoutElapsed::deadlines`]. See also [`Timeout`].
+    #[must_use]
+    pub fn label(mut self, label: impl Into<Arc<str>>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Add a context field, e.g. the id of the request the operation belongs to.
+    /// The fields are recorded on the `deadline` span of the timeout and reported in [`TimeoutElapsed::fields`].
+    /// See also [`Timeout`].
+    #[must_use]
+    pub fn field(mut self, name: &'static str, value: impl Display) -> Self {
+        self.fields.push((name, value.to_string()));
+        self
+    }
+
+    /// Make the deadline of the timeout the current deadline of the code it polls.
+    /// Timeouts nested within it are clamped to the current deadline if it is earlier than theirs,
+    /// and the time left until it can be read with [`remaining`], e.g. to size the deadlines of downstream requests.
+    /// [`TimeoutElapsed::limit`] tells whether a nested timeout elapsed at its own or at the inherited deadline.
+    /// Nested timeouts follow the deadline when the timeout is reset, and go back to their own deadlines
+    /// if it is no longer earlier than them.
+    ///
+    /// The deadline is propagated while the inner future is polled, so it does not reach spawned tasks.
+    #[must_use]
+    pub fn propagate_deadline(mut self) -> Self {
+        self.propagate = true;
+        self
+    }
+
+    /// Call `hook` if the timeout is dropped before the inner future completes and before the deadline elapses,
+    /// e.g. by an enclosing `select!`, a parent timeout or a disconnected client. Before the inner future is dropped,
+    /// it is polled one last time to capture the traces of its active await points, the same way as when the deadline elapses.
+    ///
+    /// If the inner future completes in that last poll, its output is dropped and the report has no traces.
+    /// The report has no traces either if the inner future was never polled, if that poll panics,
+    /// or if the timeout is dropped while the thread is panicking, in which case the inner future is not polled at all.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use tokio::time::sleep;
+    /// # use timeout_tracing::{CaptureSpanTrace, timeout};
+    /// # tokio::runtime::Runtime::new()
+    /// #     .unwrap()
+    /// #     .block_on(async {
+    /// let fut = timeout(Duration::from_secs(1), CaptureSpanTrace, sleep(Duration::from_secs(1)))
+    ///     .on_drop(|report| eprintln!("{report}"));
+    /// tokio::select! {
+    ///     _ = fut => {}
+    ///     () = sleep(Duration::from_millis(10)) => {}
+    /// }
+    /// # });
+    /// ```
+    #[must_use]
+    pub fn on_drop<F>(mut self, hook: F) -> Self
+    where
+        C: CaptureTrace + Send + Sync + 'static,
+        Fut: Future,
+        F: FnOnce(DropReport<C::Trace>) + Send + Sync + 'static,
+    {
+        if let Some(capture) = &self.capture {
+            self.on_drop = Some(drop_hook(capture.clone(), hook));
+        }
+        self
+    }
+
+    /// Instead of dropping the inner future when the deadline elapses, cancel `token` and give the future
+    /// `grace` more time to shut down cooperatively, e.g. to flush buffers or to roll back a transaction.
+    /// If it is still running after `grace`, the traces of the await points where the cancellation got stuck
+    /// are reported in [`TimeoutElapsed::stuck_during_cancellation`].
+    ///
+    /// The inner future should observe a clone of `token`, e.g. with [`CancellationToken::cancelled`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use tokio::time::sleep;
+    /// # use timeout_tracing::{CancellationToken, CaptureSpanTrace, timeout};
+    /// # tokio::runtime::Runtime::new()
+    /// #     .unwrap()
+    /// #     .block_on(async {
+    /// let token = CancellationToken::new();
+    /// let work = {
+    ///     let token = token.clone();
+    ///     async move {
+    ///         tokio::select! {
+    ///             () = sleep(Duration::from_secs(1)) => {}
+    ///             () = token.cancelled() => {
+    ///                 // Roll back
+    ///             }
+    ///         }
+    ///     }
+    /// };
+    /// let result = timeout(Duration::from_millis(10), CaptureSpanTrace, work)
+    ///     .grace_period(token, Duration::from_millis(100))
+    ///     .await;
+    /// assert!(result.err().unwrap().stuck_during_cancellation.is_none());
+    /// # });
+    /// ```
+    pub fn grace_period(
+        self,
+        token: CancellationToken,
+        grace: Duration,
+    ) -> GracefulTimeoutFuture<C, Fut, T>
+    where
+        C: CaptureTrace,
+    {
+        GracefulTimeoutFuture::new(self, token, grace)
+    }
+
+    /// Restore the capture taken when the deadline elapsed and move the deadline to `deadline`,
+    /// so that the elapsed timeout can be polled again.
+    pub(crate) fn rearm(mut self: Pin<&mut Self>, capture: Arc<C>, deadline: T::Instant) {
+        *self.as_mut().project().capture = Some(capture);
+        self.reset(deadline);
+    }
+
+    /// Register the future in the process-wide registry under `label`, so that it is reported by [`dump_all`].
+    pub fn register(self, label: impl Into<String>) -> RegisteredFuture<Self> {
+        let remaining = self.deadline.deadline() - T::now();
+        RegisteredFuture::new(label.into(), Some(remaining), self.stats.elapsed(), self)
+    }
+}
+
+impl<C, Fut, T> CaptureNow for TimeoutFuture<C, Fut, T>
+where
+    C: CaptureTrace + Send + 'static,
+    Fut: Future,
+    T: Timer,
+{
+    type Trace = C::Trace;
+
+    fn capture_now(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Result<Self::Output, Vec<Self::Trace>> {
+        let this = self.project();
+        let Some(capture) = this.capture else {
+            return Err(Vec::new());
+        };
+        let result = this.stats.record(cx, |cx| {
+            poll_with_capture(capture.clone(), this.forwarded, this.inner, cx).map(Ok)
+        });
+        if result.is_ok() {
+            // The inner future has completed, it is not dropped early anymore
+            *this.on_drop = None;
+        }
+        result
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        Some(self.deadline.deadline() - T::now())
+    }
+}
+
+impl<C, Fut, T> Future for TimeoutFuture<C, Fut, T>
 where
     C: CaptureTrace + Send + 'static,
     Fut: Future,
+    T: Timer,
 {
     type Output = Result<Fut::Output, TimeoutElapsed<C::Trace>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = self.project();
-        // A span just so that nested timeouts had some
-        let deadline_span = span!(Level::TRACE, "deadline");
+        let mut this = self.project();
+        if inherit_deadline(this.deadline.as_mut(), this.inherited, this.clamped) {
+            *this.version = next_version();
+        }
+        let deadline = this.deadline.deadline();
+        let remaining = deadline - T::now();
+        // Shows the limits of this timeout in the traces captured by enclosing timeouts
+        let deadline_span = span!(
+            Level::TRACE,
+            "deadline",
+            duration = ?this.duration,
+            remaining = ?remaining,
+            label = this.label.as_deref(),
+            fields = (!this.fields.is_empty()).then(|| tracing::field::display(DisplayFields(this.fields))),
+        );
         let guard = deadline_span.enter();
         match this.deadline.poll(cx) {
             Poll::Ready(()) => {
                 drop(guard);
+                // The timeout is not dropped early anymore
+                *this.on_drop = None;
+                let elapsed_deadline = DeadlineInfo {
+                    label: this.label.clone(),
+                    duration: *this.duration,
+                    remaining: Duration::ZERO,
+                };
+                let mut deadlines = enclosing_deadlines();
+                deadlines.push(elapsed_deadline.clone());
+                let _deadline_scope = DeadlineScope::enter(elapsed_deadline);
 
                 // We hit the timeout. Do one final poll for the inner future, but collect the traces this time.
                 // TODO: we don't have to call cx.waker.clone(), but it probably does not matter much since we already hit timeout
-                let Some(capture) = this.capture.take() else {
-                    return Poll::Ready(Err(TimeoutElapsed {
-                        active_traces: Vec::new(),
-                    }));
+                let active_traces = match this.capture.take() {
+                    Some(capture) => match this.stats.record(cx, |cx| {
+                        poll_with_capture(capture, this.forwarded, this.inner, cx)
+                    }) {
+                        Ok(result) => return Poll::Ready(Ok(result)),
+                        Err(active_traces) => active_traces,
+                    },
+                    None => Vec::new(),
                 };
-                let waker_inner = TracingTimeoutWakerInner::new(capture, cx.waker().clone());
-                let waker = TracingTimeoutWaker::new_std_waker(waker_inner.clone());
-                let mut cx2 = Context::from_waker(&waker);
-                match this.inner.poll(&mut cx2) {
-                    Poll::Pending => {}
-                    Poll::Ready(result) => return Poll::Ready(Ok(result)),
-                }
-                let active_traces: Vec<_> = waker_inner.traces();
-                return Poll::Ready(Err(TimeoutElapsed { active_traces }));
+                return Poll::Ready(Err(TimeoutElapsed {
+                    active_traces,
+                    label: this.label.clone(),
+                    fields: this.fields.clone(),
+                    duration: *this.duration,
+                    elapsed: this.stats.elapsed(),
+                    polls: this.stats.stats(),
+                    deadlines,
+                    limit: if this.clamped.is_some() {
+                        Limit::Inherited
+                    } else {
+                        Limit::Local
+                    },
+                    blocked_in_poll: this.stats.take_blocked(),
+                    stuck_during_cancellation: None,
+                }));
             }
             Poll::Pending => {}
         }
         drop(guard);
-        match this.inner.poll(cx) {
+        let _forward_scope = ForwardScope::enter_if_needed(this.forwarded);
+        let _deadline_scope = DeadlineScope::enter(DeadlineInfo {
+            label: this.label.clone(),
+            duration: *this.duration,
+            remaining,
+        });
+        let _current_deadline_scope = this
+            .propagate
+            .then(|| CurrentDeadlineScope::enter::<T>(&deadline, *this.version));
+        match this.stats.record(cx, |cx| this.inner.poll(cx)) {
             Poll::Pending => Poll::Pending,
-            Poll::Ready(result) => Poll::Ready(Ok(result)),
+            Poll::Ready(result) => {
+                *this.on_drop = None;
+                Poll::Ready(Ok(result))
+            }
         }
     }
 }
 
+/// Clamps `deadline` to the current deadline propagated by an enclosing timeout if it is earlier,
+/// or moves it back to the own deadline of the timeout if the propagated one is not earlier anymore.
+/// Only does anything when the propagated deadline has changed since it was last applied, e.g. when
+/// the enclosing timeout was reset. Returns whether `deadline` was reset.
+fn inherit_deadline<T: Timer>(
+    deadline: Pin<&mut T>,
+    inherited: &mut Option<u64>,
+    clamped: &mut Option<Duration>,
+) -> bool {
+    // The current time is read first, so that the inherited deadline is not later than the propagated one
+    // if both timers use the same clock
+    let now = T::now();
+    let Some(current) = current_deadline() else {
+        return false;
+    };
+    if *inherited == Some(current.version) {
+        return false;
+    }
+    *inherited = Some(current.version);
+    let was_clamped = clamped.is_some();
+    let own = deadline.deadline() + clamped.take().unwrap_or_default();
+    if current.remaining < own - now {
+        let inherited = now + current.remaining;
+        *clamped = Some(own - inherited);
+        deadline.reset(inherited);
+        true
+    } else if was_clamped {
+        deadline.reset(own);
+        true
+    } else {
+        false
+    }
+}
+
 #[derive(Debug)]
 pub struct TimeoutElapsed<Trace> {
     pub active_traces: Vec<Trace>,
+    /// The label set with [`Timeout::label`] or [`TimeoutFuture::label`].
+    pub label: Option<Arc<str>>,
+    /// The context fields set with [`Timeout::field`] or [`TimeoutFuture::field`], in the order they were added.
+    pub fields: Vec<(&'static str, String)>,
+    /// The time limit of the timeout.
+    pub duration: Duration,
+    /// Wall-clock time from the creation of the timeout to its expiration.
+    pub elapsed: Duration,
+    /// How the inner future was polled.
+    pub polls: PollStats,
+    /// The chain of nested timeouts that were polling the elapsed one, from the outermost one
+    /// to the elapsed timeout itself, which is always the last.
+    pub deadlines: Vec<DeadlineInfo>,
+    /// Whether the timeout elapsed at its own deadline or at the earlier one inherited from an enclosing timeout,
+    /// see [`TimeoutFuture::propagate_deadline`].
+    pub limit: Limit,
+    /// The longest poll of the inner future that blocked the thread, if blocking is detected
+    /// with [`TimeoutFuture::detect_blocking`]. When it is set, the timeout most likely fired late
+    /// because the thread was blocked, and [`Self::active_traces`] don't show where the time was spent.
+    pub blocked_in_poll: Option<BlockedPoll>,
+    /// Traces of the await points that were still active when the grace period after the cancellation elapsed,
+    /// see [`TimeoutFuture::grace_period`]. `None` if there was no grace period or the future finished within it.
+    pub stuck_during_cancellation: Option<Vec<Trace>>,
+}
+
+impl<Trace> TimeoutElapsed<Trace> {
+    /// Returns `true` if the elapsed timeout is nested within other timeouts,
+    /// i.e. the inner timeout elapsed before the outer ones.
+    #[must_use]
+    pub fn is_inner(&self) -> bool {
+        self.deadlines.len() > 1
+    }
+}
+
+impl<Trace: AsSpanTrace> TimeoutElapsed<Trace> {
+    /// Merge the span traces of all active await points into a single tree.
+    /// See [`AwaitTree`].
+    #[must_use]
+    pub fn await_tree(&self) -> AwaitTree {
+        AwaitTree::from_traces(&self.active_traces)
+    }
 }
 
 impl<Trace: Display> Display for TimeoutElapsed<Trace> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(blocked) = &self.blocked_in_poll {
+            writeln!(
+                f,
+                "thread blocked in poll for {:?} at:\n{}",
+                blocked.duration, blocked.stack_trace
+            )?;
+        }
+        if self.limit == Limit::Inherited {
+            writeln!(
+                f,
+                "the deadline inherited from an enclosing timeout elapsed before the {:?} limit",
+                self.duration
+            )?;
+        }
+        if self.is_inner() {
+            f.write_str("inner timeout elapsed, deadlines from the outermost one:\n")?;
+            for (idx, deadline) in self.deadlines.iter().enumerate() {
+                writeln!(f, "{idx:4}: {deadline}")?;
+            }
+        }
+        f.write_str("timeout")?;
+        if let Some(label) = &self.label {
+            write!(f, " {label}")?;
+        }
+        if !self.fields.is_empty() {
+            write!(f, "{{{}}}", DisplayFields(&self.fields))?;
+        }
         if self.active_traces.is_empty() {
-            f.write_str("timeout elapsed")?;
+            f.write_str(" elapsed")?;
         } else {
-            f.write_str("timeout elapsed at:\n")?;
+            f.write_str(" elapsed at:\n")?;
             for (idx, trace) in self.active_traces.iter().enumerate() {
                 writeln!(f, "trace {idx}:\n{trace}")?;
             }
         }
+        if let Some(stuck) = &self.stuck_during_cancellation {
+            f.write_str("\nstuck during cancellation at:\n")?;
+            for (idx, trace) in stuck.iter().enumerate() {
+                writeln!(f, "trace {idx}:\n{trace}")?;
+            }
+        }
         Ok(())
     }
 }
 
 impl<Trace> Error for TimeoutElapsed<Trace> where Trace: std::fmt::Debug + std::fmt::Display {}
+
+#[cfg(feature = "serde")]
+impl<Trace: SerializeTrace> serde::Serialize for TimeoutElapsed<Trace> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("TimeoutElapsed", 10)?;
+        state.serialize_field("active_traces", &SerializeAsTraces(&self.active_traces))?;
+        state.serialize_field("label", &self.label)?;
+        state.serialize_field("fields", &SerializeFields(&self.fields))?;
+        state.serialize_field("duration", &self.duration)?;
+        state.serialize_field("elapsed", &self.elapsed)?;
+        state.serialize_field("polls", &self.polls)?;
+        state.serialize_field("deadlines", &self.deadlines)?;
+        state.serialize_field("limit", &self.limit)?;
+        state.serialize_field("blocked_in_poll", &self.blocked_in_poll)?;
+        state.serialize_field(
+            "stuck_during_cancellation",
+            &self
+                .stuck_during_cancellation
+                .as_deref()
+                .map(SerializeAsTraces),
+        )?;
+        state.end()
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use crate::{
+    filter::FrameFilter,
+    stack::{LazyFrames, StackFrame, short_path},
+    trace::CaptureTrace,
+};
+
+/// Implementation of [`CaptureTrace`] that captures the source locations of the await points within user code.
+///
+/// This is a middle ground between [`crate::CaptureSpanTrace`], which only shows instrumented functions,
+/// and [`crate::CaptureSpanAndStackTrace`], which shows every frame and requires `RUST_BACKTRACE`.
+/// The stack is captured without resolving symbols, which is cheap, and is resolved only when the trace
+/// is displayed or its locations are accessed. Only frames of the code being built are kept
+/// (see [`FrameFilter::user_code_only`]), so un-instrumented functions between the instrumented ones
+/// and the leaf resource are shown as well.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureAwaitLocations, timeout};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let result = timeout(Duration::from_millis(10), CaptureAwaitLocations::new(), async {
+///     sleep(Duration::from_secs(1)).await;
+/// })
+/// .await;
+/// let elapsed = result.err().unwrap();
+/// assert_eq!(elapsed.active_traces.len(), 1);
+/// println!("{elapsed}");
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct CaptureAwaitLocations {
+    filter: Arc<FrameFilter>,
+}
+
+impl Default for CaptureAwaitLocations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CaptureAwaitLocations {
+    /// Capture the await locations filtered with the default [`FrameFilter`] and [`FrameFilter::user_code_only`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_filter(FrameFilter::new().user_code_only(true))
+    }
+
+    /// Capture the await locations filtered with `filter`.
+    #[must_use]
+    pub fn with_filter(filter: FrameFilter) -> Self {
+        Self {
+            filter: Arc::new(filter),
+        }
+    }
+}
+
+impl CaptureTrace for CaptureAwaitLocations {
+    type Trace = AwaitLocations;
+
+    #[inline(never)]
+    fn capture(&self) -> Self::Trace {
+        AwaitLocations {
+            frames: LazyFrames::new(backtrace::Backtrace::new_unresolved(), self.filter.clone()),
+        }
+    }
+}
+
+/// Source locations of an await point and of the functions awaiting it, captured by [`CaptureAwaitLocations`].
+pub struct AwaitLocations {
+    frames: LazyFrames,
+}
+
+impl AwaitLocations {
+    /// Returns the locations, starting from the innermost one. Resolves the symbols on first call.
+    #[must_use]
+    pub fn locations(&self) -> &[StackFrame] {
+        self.frames.frames()
+    }
+}
+
+impl Display for AwaitLocations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, frame) in self.locations().iter().enumerate() {
+            write!(f, "{idx:4}: ")?;
+            if let Some(file) = &frame.file {
+                write!(f, "{}", short_path(file).display())?;
+                if let Some(line) = frame.line {
+                    write!(f, ":{line}")?;
+                    if let Some(column) = frame.column {
+                        write!(f, ":{column}")?;
+                    }
+                }
+            }
+            writeln!(
+                f,
+                " in {}",
+                frame.function.as_deref().unwrap_or("<unknown>")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for AwaitLocations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AwaitLocations ")?;
+        f.debug_list().entries(self.locations()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl crate::SerializeTrace for AwaitLocations {
+    fn serialize_trace<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.locations())
+    }
+}
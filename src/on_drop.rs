@@ -0,0 +1,80 @@
+use std::{
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use crate::{forward::ForwardedWakers, trace::CaptureTrace, waker::poll_with_capture};
+
+/// A report about a [`crate::TimeoutFuture`] that was dropped before it completed or timed out,
+/// e.g. by an enclosing `select!` or timeout. Passed to the hook set with [`crate::TimeoutFuture::on_drop`].
+#[derive(Debug)]
+pub struct DropReport<Trace> {
+    /// Time since the timeout was created.
+    pub elapsed: Duration,
+    pub active_traces: Vec<Trace>,
+}
+
+impl<Trace: Display> Display for DropReport<Trace> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "future dropped after {:?}", self.elapsed)?;
+        if !self.active_traces.is_empty() {
+            f.write_str(" at:\n")?;
+            for (idx, trace) in self.active_traces.iter().enumerate() {
+                writeln!(f, "trace {idx}:\n{trace}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<Trace: crate::SerializeTrace> serde::Serialize for DropReport<Trace> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("DropReport", 2)?;
+        state.serialize_field("elapsed", &self.elapsed)?;
+        state.serialize_field(
+            "active_traces",
+            &crate::trace::SerializeAsTraces(&self.active_traces),
+        )?;
+        state.end()
+    }
+}
+
+/// Captures the traces of the inner future that is about to be dropped and passes them to the user hook.
+/// Erases the trace type, so that [`crate::TimeoutFuture`] can store it without bounds on its type parameters.
+/// The inner future is `None` if it must not be polled.
+pub(crate) type DropHook<Fut> =
+    Box<dyn FnOnce(Option<Pin<&mut Fut>>, &Arc<ForwardedWakers>, Duration) + Send + Sync>;
+
+pub(crate) fn drop_hook<C, Fut, F>(capture: Arc<C>, hook: F) -> DropHook<Fut>
+where
+    C: CaptureTrace + Send + Sync + 'static,
+    Fut: Future,
+    F: FnOnce(DropReport<C::Trace>) + Send + Sync + 'static,
+{
+    Box::new(move |inner, forwarded, elapsed| {
+        // Nobody is going to poll the future again, so there is no task to wake
+        let cx = Context::from_waker(Waker::noop());
+        // A future that completes in this poll is dropped with its output.
+        // A panic of the future must not unwind out of the drop of the timeout.
+        let active_traces = inner
+            .and_then(|inner| {
+                panic::catch_unwind(AssertUnwindSafe(|| {
+                    poll_with_capture(capture, forwarded, inner, &cx)
+                }))
+                .ok()
+            })
+            .and_then(Result::err)
+            .unwrap_or_default();
+        hook(DropReport {
+            elapsed,
+            active_traces,
+        });
+    })
+}
@@ -0,0 +1,420 @@
+use std::{
+    fmt::Display,
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant, SystemTime},
+};
+
+use pin_project_lite::pin_project;
+use tracing::warn;
+
+use crate::{
+    TimeoutFuture, WatchdogFuture,
+    timer::{DefaultTimer, Timer},
+    trace::CaptureTrace,
+    watchdog::ReportStall,
+};
+
+/// Futures that can capture the traces of their await points on demand.
+pub(crate) trait CaptureNow: Future {
+    type Trace;
+
+    /// Poll the future once while capturing the traces of its await points.
+    /// Returns `Ok` with the output if the future completes during this poll.
+    fn capture_now(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Result<Self::Output, Vec<Self::Trace>>;
+
+    /// Time left until the future times out, if it has a time limit.
+    fn remaining(&self) -> Option<Duration>;
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    entries: Vec::new(),
+    prune_at: 0,
+});
+
+/// Entries of the futures registered in the process. The entries of dropped futures are pruned
+/// by [`dump_all`] or once the number of entries doubles, so that registering stays cheap.
+struct Registry {
+    entries: Vec<Weak<Entry>>,
+    prune_at: usize,
+}
+
+impl Registry {
+    fn push(&mut self, entry: Weak<Entry>) {
+        if self.entries.len() >= self.prune_at {
+            self.prune();
+        }
+        self.entries.push(entry);
+    }
+
+    fn prune(&mut self) {
+        self.entries.retain(|entry| entry.strong_count() > 0);
+        self.prune_at = (self.entries.len() * 2).max(64);
+    }
+}
+
+/// Locks ignoring poisoning, so that a panicking task does not break [`dump_all`] for the whole process.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Registry entry of a single [`RegisteredFuture`].
+struct Entry {
+    label: String,
+    started: SystemTime,
+    started_instant: Instant,
+    state: Mutex<EntryState>,
+}
+
+struct EntryState {
+    deadline: Option<Instant>,
+    /// The waker of the last poll of the future
+    waker: Option<Waker>,
+    requests: Vec<Arc<DumpRequest>>,
+}
+
+impl Entry {
+    /// Returns the deadline and the time left until it.
+    fn deadline(&self) -> Option<(SystemTime, Duration)> {
+        let deadline = lock(&self.state).deadline?;
+        Some((
+            self.started + (deadline - self.started_instant),
+            deadline.saturating_duration_since(Instant::now()),
+        ))
+    }
+}
+
+/// A request of a single [`dump_all`] call to a single registered future.
+struct DumpRequest {
+    result: Mutex<DumpResult>,
+    dumper: Arc<Mutex<Option<Waker>>>,
+}
+
+enum DumpResult {
+    Pending,
+    Captured(Vec<String>),
+    /// The future completed or was dropped before capturing the traces
+    Completed,
+}
+
+impl DumpRequest {
+    fn respond(&self, result: DumpResult) {
+        *lock(&self.result) = result;
+        if let Some(waker) = &*lock(&self.dumper) {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+pin_project! {
+    /// A future registered in the process-wide registry, so that it is reported by [`dump_all`].
+    /// Created by [`TimeoutFuture::register`].
+    pub struct RegisteredFuture<F> {
+        registration: Registration,
+        #[pin]
+        inner: F,
+    }
+}
+
+/// Removes the entry from the registry when the future is dropped.
+struct Registration {
+    entry: Arc<Entry>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let requests = mem::take(&mut lock(&self.entry.state).requests);
+        for request in requests {
+            request.respond(DumpResult::Completed);
+        }
+    }
+}
+
+impl<F> RegisteredFuture<F> {
+    /// `elapsed` is the time since `inner` was created, which may have been well before it was registered.
+    pub(crate) fn new(
+        label: String,
+        remaining: Option<Duration>,
+        elapsed: Duration,
+        inner: F,
+    ) -> Self {
+        let now = Instant::now();
+        let entry = Arc::new(Entry {
+            label,
+            started: SystemTime::now() - elapsed,
+            started_instant: now.checked_sub(elapsed).unwrap_or(now),
+            state: Mutex::new(EntryState {
+                deadline: remaining.map(|remaining| now + remaining),
+                waker: None,
+                requests: Vec::new(),
+            }),
+        });
+        lock(&REGISTRY).push(Arc::downgrade(&entry));
+        Self {
+            registration: Registration { entry },
+            inner,
+        }
+    }
+
+    /// Returns the label of the future.
+    #[must_use]
+    pub fn label(&self) -> &str {
+        &self.registration.entry.label
+    }
+
+    /// Returns a pinned mutable reference to the registered future, e.g. to call [`TimeoutFuture::reset`].
+    #[must_use]
+    pub fn inner_pin_mut(self: Pin<&mut Self>) -> Pin<&mut F> {
+        self.project().inner
+    }
+}
+
+fn poll_registered<F>(
+    future: Pin<&mut RegisteredFuture<F>>,
+    cx: &mut Context<'_>,
+) -> Poll<F::Output>
+where
+    F: CaptureNow,
+    F::Trace: Display,
+{
+    let mut this = future.project();
+    let requests = {
+        let mut state = lock(&this.registration.entry.state);
+        state.deadline = this
+            .inner
+            .remaining()
+            .map(|remaining| Instant::now() + remaining);
+        if !state
+            .waker
+            .as_ref()
+            .is_some_and(|waker| waker.will_wake(cx.waker()))
+        {
+            state.waker = Some(cx.waker().clone());
+        }
+        mem::take(&mut state.requests)
+    };
+    if !requests.is_empty() {
+        match this.inner.as_mut().capture_now(cx) {
+            Ok(output) => {
+                for request in requests {
+                    request.respond(DumpResult::Completed);
+                }
+                return Poll::Ready(output);
+            }
+            Err(traces) => {
+                let traces: Vec<String> = traces.iter().map(ToString::to_string).collect();
+                for request in requests {
+                    request.respond(DumpResult::Captured(traces.clone()));
+                }
+                // The capturing wakers stored by the await points wake the task just like the wakers of a plain poll
+                return Poll::Pending;
+            }
+        }
+    }
+    this.inner.poll(cx)
+}
+
+impl<C, Fut, T> Future for RegisteredFuture<TimeoutFuture<C, Fut, T>>
+where
+    C: CaptureTrace + Send + 'static,
+    C::Trace: Display,
+    Fut: Future,
+    T: Timer,
+{
+    type Output = <TimeoutFuture<C, Fut, T> as Future>::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        poll_registered(self, cx)
+    }
+}
+
+impl<C, R, Fut, T> Future for RegisteredFuture<WatchdogFuture<C, R, Fut, T>>
+where
+    C: CaptureTrace + Send + 'static,
+    C::Trace: Display,
+    R: ReportStall<C::Trace>,
+    Fut: Future,
+    T: Timer,
+{
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        poll_registered(self, cx)
+    }
+}
+
+/// The state of a registered future, returned by [`dump_all`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FutureDump {
+    /// The label passed to [`TimeoutFuture::register`].
+    pub label: String,
+    /// When the timeout or watchdog was created, which may be before it was registered.
+    pub started: SystemTime,
+    /// When the future times out.
+    pub deadline: Option<SystemTime>,
+    /// Time left until the future times out.
+    pub remaining: Option<Duration>,
+    /// Formatted traces of the active await points of the future,
+    /// or `None` if the future was not polled within the time given to [`dump_all_within`].
+    pub active_traces: Option<Vec<String>>,
+}
+
+impl Display for FutureDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)?;
+        if let Ok(elapsed) = self.started.elapsed() {
+            write!(f, ", running for {elapsed:?}")?;
+        }
+        if let Some(remaining) = self.remaining {
+            write!(f, ", {remaining:?} until timeout")?;
+        }
+        match &self.active_traces {
+            None => f.write_str(", not polled\n"),
+            Some(traces) if traces.is_empty() => f.write_str(", no await points\n"),
+            Some(traces) => {
+                f.write_str(", awaiting at:\n")?;
+                for (idx, trace) in traces.iter().enumerate() {
+                    writeln!(f, "trace {idx}:\n{trace}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Capture the active await points of all [`RegisteredFuture`]s that are alive in the process.
+/// Same as [`dump_all_within`] with 1 second.
+pub async fn dump_all() -> Vec<FutureDump> {
+    dump_all_within(Duration::from_secs(1)).await
+}
+
+/// Capture the active await points of all [`RegisteredFuture`]s that are alive in the process.
+///
+/// Each future is woken and captures its traces the next time it is polled.
+/// Futures that are not polled within `max_wait` (e.g. because their task is blocked)
+/// are reported without traces. Futures that complete in the meantime are not reported.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, dump_all, timeout};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// tokio::spawn(
+///     timeout(Duration::from_secs(10), CaptureSpanTrace, sleep(Duration::from_secs(5)))
+///         .register("sleep"),
+/// );
+/// sleep(Duration::from_millis(10)).await;
+/// let dumps = dump_all().await;
+/// assert_eq!(dumps.len(), 1);
+/// assert_eq!(dumps[0].label, "sleep");
+/// assert_eq!(dumps[0].active_traces.as_ref().unwrap().len(), 1);
+/// # });
+/// ```
+pub async fn dump_all_within(max_wait: Duration) -> Vec<FutureDump> {
+    let entries: Vec<Arc<Entry>> = {
+        let mut registry = lock(&REGISTRY);
+        registry.prune();
+        registry.entries.iter().filter_map(Weak::upgrade).collect()
+    };
+    let dumper = Arc::new(Mutex::new(None));
+    let requests: Vec<Arc<DumpRequest>> = entries
+        .iter()
+        .map(|entry| {
+            let request = Arc::new(DumpRequest {
+                result: Mutex::new(DumpResult::Pending),
+                dumper: dumper.clone(),
+            });
+            let mut state = lock(&entry.state);
+            state.requests.push(request.clone());
+            if let Some(waker) = &state.waker {
+                waker.wake_by_ref();
+            }
+            request
+        })
+        .collect();
+
+    let mut timer = std::pin::pin!(DefaultTimer::sleep(max_wait));
+    std::future::poll_fn(|cx| {
+        *lock(&dumper) = Some(cx.waker().clone());
+        let pending = requests
+            .iter()
+            .any(|request| matches!(*lock(&request.result), DumpResult::Pending));
+        if pending && timer.as_mut().poll(cx).is_pending() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+
+    entries
+        .iter()
+        .zip(requests)
+        .filter_map(|(entry, request)| {
+            let active_traces =
+                match mem::replace(&mut *lock(&request.result), DumpResult::Completed) {
+                    DumpResult::Pending => None,
+                    DumpResult::Captured(traces) => Some(traces),
+                    DumpResult::Completed => return None,
+                };
+            let deadline = entry.deadline();
+            Some(FutureDump {
+                label: entry.label.clone(),
+                started: entry.started,
+                deadline: deadline.map(|(deadline, _)| deadline),
+                remaining: deadline.map(|(_, remaining)| remaining),
+                active_traces,
+            })
+        })
+        .collect()
+}
+
+/// A trait for handling the [`FutureDump`]s of a single [`dump_all`] call, e.g. one triggered by [`crate::dump_on_signal`].
+/// It is implemented for closures accepting a `Vec<FutureDump>`, for [`LogDump`] and for [`PrintDump`].
+pub trait ReportDump {
+    fn report(&mut self, dumps: Vec<FutureDump>);
+}
+
+impl<F> ReportDump for F
+where
+    F: FnMut(Vec<FutureDump>),
+{
+    fn report(&mut self, dumps: Vec<FutureDump>) {
+        self(dumps);
+    }
+}
+
+/// Implementation of [`ReportDump`] that emits a `WARN` event via [`tracing`] for each registered future.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogDump;
+
+impl ReportDump for LogDump {
+    fn report(&mut self, dumps: Vec<FutureDump>) {
+        for dump in dumps {
+            warn!(label = dump.label, remaining = ?dump.remaining, "{dump}");
+        }
+    }
+}
+
+/// Implementation of [`ReportDump`] that writes all registered futures to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrintDump;
+
+impl ReportDump for PrintDump {
+    fn report(&mut self, dumps: Vec<FutureDump>) {
+        let mut out = format!("{} registered futures\n", dumps.len());
+        for dump in dumps {
+            out.push_str(&dump.to_string());
+        }
+        eprint!("{out}");
+    }
+}
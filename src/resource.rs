@@ -0,0 +1,264 @@
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    pin::Pin,
+    sync::{Arc, LazyLock, OnceLock},
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+use tracing_error::SpanTrace;
+
+use crate::{
+    filter::{FrameFilter, function},
+    stack::{LazyFrames, StackFrame},
+    trace::CaptureTrace,
+    tree::AsSpanTrace,
+};
+
+/// Implementation of [`CaptureTrace`] that wraps the traces of `C` with the classification of the leaf resource
+/// the await point is waiting on, e.g. `tokio::time::Sleep` or `Semaphore acquire`.
+/// Created by [`CaptureTrace::with_resource`].
+///
+/// The resource is taken from the innermost [`label_resource`] wrapper being polled, if there is one.
+/// Otherwise it is recognized by the stack frames of the code that stored the waker. The stack is captured
+/// without resolving its symbols and classified only when the trace is displayed or [`ResourceTrace::resource`] is called.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureResource<C> {
+    inner: C,
+}
+
+impl<C> CaptureResource<C> {
+    pub(crate) fn new(inner: C) -> Self {
+        Self { inner }
+    }
+}
+
+impl<C: CaptureTrace> CaptureTrace for CaptureResource<C> {
+    type Trace = ResourceTrace<C::Trace>;
+
+    #[inline(never)]
+    fn capture(&self) -> Self::Trace {
+        static FILTER: LazyLock<Arc<FrameFilter>> = LazyLock::new(Arc::default);
+        let source = match LABELS.with_borrow(|labels| labels.last().cloned()) {
+            Some(label) => Source::Labeled(label),
+            None => Source::Frames {
+                frames: LazyFrames::new(backtrace::Backtrace::new_unresolved(), FILTER.clone()),
+                resource: OnceLock::new(),
+            },
+        };
+        ResourceTrace {
+            source,
+            trace: self.inner.capture(),
+        }
+    }
+}
+
+/// A trace captured by [`CaptureResource`].
+pub struct ResourceTrace<Trace> {
+    source: Source,
+    trace: Trace,
+}
+
+enum Source {
+    Labeled(Arc<str>),
+    Frames {
+        frames: LazyFrames,
+        resource: OnceLock<Option<&'static str>>,
+    },
+}
+
+impl<Trace> ResourceTrace<Trace> {
+    /// Returns the resource the await point is waiting on, or `None` if it is not recognized.
+    #[must_use]
+    pub fn resource(&self) -> Option<&str> {
+        match &self.source {
+            Source::Labeled(label) => Some(label),
+            Source::Frames { frames, resource } => {
+                *resource.get_or_init(|| classify(frames.frames()))
+            }
+        }
+    }
+
+    /// Returns the wrapped trace.
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Returns the wrapped trace.
+    pub fn into_trace(self) -> Trace {
+        self.trace
+    }
+}
+
+impl<Trace: Display> Display for ResourceTrace<Trace> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "waiting on {}",
+            self.resource().unwrap_or("<unknown resource>")
+        )?;
+        Display::fmt(&self.trace, f)
+    }
+}
+
+impl<Trace: Debug> Debug for ResourceTrace<Trace> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceTrace")
+            .field("resource", &self.resource())
+            .field("trace", &self.trace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Trace: AsSpanTrace> AsSpanTrace for ResourceTrace<Trace> {
+    fn as_span_trace(&self) -> &SpanTrace {
+        self.trace.as_span_trace()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<Trace: crate::SerializeTrace> crate::SerializeTrace for ResourceTrace<Trace> {
+    fn serialize_trace<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("ResourceTrace", 2)?;
+        state.serialize_field("resource", &self.resource())?;
+        state.serialize_field("trace", &crate::trace::SerializeAsTrace(&self.trace))?;
+        state.end()
+    }
+}
+
+/// Known resources, matched against the function names of the frames that stored the waker.
+/// More specific patterns go first, e.g. `Mutex` and `mpsc::Sender` are implemented with a semaphore.
+/// Channels are matched by the side that waits, as both sides share the implementation of the channel.
+const RESOURCES: &[(&str, &str)] = &[
+    ("tokio::time::sleep::Sleep", "tokio::time::Sleep"),
+    ("tokio::time::interval::Interval", "tokio::time::Interval"),
+    ("tokio::sync::mutex::", "Mutex lock"),
+    ("tokio::sync::rwlock::", "RwLock lock"),
+    ("tokio::sync::mpsc::bounded::Sender", "mpsc::Sender send"),
+    ("tokio::sync::mpsc::chan::Rx", "mpsc::Receiver"),
+    ("tokio::sync::oneshot::Sender", "oneshot::Sender closed"),
+    ("tokio::sync::oneshot::Receiver", "oneshot::Receiver"),
+    ("tokio::sync::broadcast::Receiver", "broadcast::Receiver"),
+    ("tokio::sync::watch::Sender", "watch::Sender closed"),
+    ("tokio::sync::watch::Receiver", "watch::Receiver"),
+    ("tokio::sync::notify::", "Notify"),
+    ("tokio::sync::semaphore::", "Semaphore acquire"),
+    ("tokio::sync::batch_semaphore::", "Semaphore acquire"),
+    ("tokio::runtime::task::join::JoinHandle", "JoinHandle"),
+    (
+        "tokio::net::tcp::listener::TcpListener",
+        "TcpListener accept",
+    ),
+    (
+        "tokio::net::tcp::stream::TcpStream::connect",
+        "TcpStream connect",
+    ),
+    (
+        "tokio::net::tcp::socket::TcpSocket::connect",
+        "TcpStream connect",
+    ),
+    ("tokio::net::tcp::stream::TcpStream", "TcpStream"),
+    ("tokio::net::udp::UdpSocket", "UdpSocket"),
+    ("tokio::net::unix::", "Unix socket"),
+    ("tokio::process::", "child process"),
+    ("tokio::fs::", "file IO"),
+    ("futures_channel::mpsc::Sender", "mpsc::Sender send"),
+    ("futures_channel::mpsc::Receiver", "mpsc::Receiver"),
+    ("futures_channel::mpsc::UnboundedReceiver", "mpsc::Receiver"),
+    ("futures_channel::oneshot::Sender", "oneshot::Sender closed"),
+    ("futures_channel::oneshot::Receiver", "oneshot::Receiver"),
+];
+
+fn classify(frames: &[StackFrame]) -> Option<&'static str> {
+    // Only the frames of the leaf resource, not the frames of the futures awaiting it
+    let leaf = &frames[..frames.len().min(LEAF_FRAMES)];
+    let (_, resource) = RESOURCES
+        .iter()
+        .find(|(pattern, _)| leaf.iter().any(|frame| function(frame).contains(pattern)))?;
+    if *resource == "TcpStream" {
+        // Tell reads from writes by the readiness the stream waits for
+        return Some(
+            if leaf
+                .iter()
+                .any(|frame| function(frame).contains("poll_write_ready"))
+            {
+                "TcpStream write"
+            } else {
+                "TcpStream read"
+            },
+        );
+    }
+    Some(resource)
+}
+
+/// Resources are rarely implemented with more nested frames than this.
+const LEAF_FRAMES: usize = 12;
+
+thread_local! {
+    static LABELS: RefCell<Vec<Arc<str>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Label the leaf resource awaited by `inner` for [`CaptureResource`].
+/// Use it for resources that are not recognized by their stack frames, e.g. clients of custom protocols.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, CaptureTrace, label_resource, timeout};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let result = timeout(
+///     Duration::from_millis(10),
+///     CaptureSpanTrace.with_resource(),
+///     label_resource("inventory RPC", sleep(Duration::from_secs(1))),
+/// )
+/// .await;
+/// let elapsed = result.err().unwrap();
+/// assert_eq!(elapsed.active_traces[0].resource(), Some("inventory RPC"));
+/// # });
+/// ```
+pub fn label_resource<Fut>(label: impl Into<Arc<str>>, inner: Fut) -> LabeledResource<Fut> {
+    LabeledResource {
+        label: label.into(),
+        inner,
+    }
+}
+
+pin_project! {
+    /// Future returned by [`label_resource`].
+    pub struct LabeledResource<Fut> {
+        label: Arc<str>,
+        #[pin]
+        inner: Fut,
+    }
+}
+
+impl<Fut: Future> Future for LabeledResource<Fut> {
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let _scope = LabelScope::enter(this.label);
+        this.inner.poll(cx)
+    }
+}
+
+struct LabelScope;
+
+impl LabelScope {
+    fn enter(label: &Arc<str>) -> Self {
+        LABELS.with_borrow_mut(|labels| labels.push(label.clone()));
+        Self
+    }
+}
+
+impl Drop for LabelScope {
+    fn drop(&mut self) {
+        LABELS.with_borrow_mut(Vec::pop);
+    }
+}
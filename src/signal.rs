@@ -0,0 +1,42 @@
+use std::io;
+
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    task::JoinHandle,
+};
+
+use crate::registry::{ReportDump, dump_all};
+
+/// Pass the active await points of all registered futures (see [`crate::TimeoutFuture::register`]
+/// and [`crate::WatchdogFuture::register`]) to `reporter` every time the process receives the signal `kind`,
+/// like a thread dump of the JVM. Returns the handle of the task that listens for the signal,
+/// abort it to stop listening.
+///
+/// Must be called within a Tokio runtime with the IO driver enabled.
+///
+/// # Errors
+///
+/// Returns an error if the signal handler cannot be installed.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use tokio::signal::unix::SignalKind;
+/// # use timeout_tracing::{PrintDump, dump_on_signal};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// // `kill -USR1 <pid>` writes all in-flight await points to stderr
+/// dump_on_signal(SignalKind::user_defined1(), PrintDump).unwrap();
+/// # });
+/// ```
+pub fn dump_on_signal<R>(kind: SignalKind, mut reporter: R) -> io::Result<JoinHandle<()>>
+where
+    R: ReportDump + Send + 'static,
+{
+    let mut signal = signal(kind)?;
+    Ok(tokio::spawn(async move {
+        while signal.recv().await.is_some() {
+            reporter.report(dump_all().await);
+        }
+    }))
+}
@@ -0,0 +1,173 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError},
+    task::{Context, Poll, Waker},
+};
+
+use tokio::task::{JoinError, JoinHandle};
+use tracing::Instrument;
+
+use crate::waker::{capturing_waker, is_capturing};
+
+/// Spawn a task with [`tokio::spawn`] whose await points are included in the traces of a timeout that awaits it.
+///
+/// When a [`crate::TimeoutFuture`] or [`crate::WatchdogFuture`] captures traces while awaiting
+/// the returned [`TracedJoinHandle`], the spawned task is polled once more with a waker that captures
+/// the traces of its await points, so they are reported alongside the traces of the awaiting future.
+/// The task is also instrumented with the current span, so its spans are nested within the spans of the caller.
+///
+/// # Limitations
+///
+/// The poll that captures the traces runs on the task that awaits the [`TracedJoinHandle`], not on the spawned task.
+/// Within it, [`tokio::task::id`], task-local values and the cooperative scheduling budget are those of the awaiting task,
+/// so code that depends on them may behave differently in that one poll. If the spawned task is being polled
+/// by the runtime at the same moment, its await points are not captured.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, spawn_traced, timeout};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let task = spawn_traced(async {
+///     sleep(Duration::from_secs(1)).await;
+/// });
+/// let result = timeout(Duration::from_millis(10), CaptureSpanTrace, task).await;
+/// // One trace for the `sleep` within the task and one for the `JoinHandle`
+/// assert_eq!(result.err().unwrap().active_traces.len(), 2);
+/// # });
+/// ```
+pub fn spawn_traced<Fut>(fut: Fut) -> TracedJoinHandle<Fut::Output>
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(TaskState {
+        future: Some(Box::pin(fut.in_current_span())),
+        output: None,
+        panic: None,
+        task_waker: None,
+    }));
+    let handle = tokio::spawn(TracedTask {
+        state: state.clone(),
+    });
+    TracedJoinHandle { handle, state }
+}
+
+struct TaskState<T> {
+    future: Option<Pin<Box<dyn Future<Output = T> + Send>>>,
+    /// Output of the future if it completed while being polled by [`TracedJoinHandle`]
+    output: Option<T>,
+    /// Payload of the panic of the future if it panicked while being polled by [`TracedJoinHandle`],
+    /// resumed within the spawned task so that it is reported as a [`JoinError`]
+    panic: Option<Box<dyn Any + Send>>,
+    /// Waker of the spawned task
+    task_waker: Option<Waker>,
+}
+
+/// The future that is actually spawned. The inner future is shared with [`TracedJoinHandle`].
+struct TracedTask<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> Future for TracedTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.state);
+        if let Some(payload) = state.panic.take() {
+            drop(state);
+            panic::resume_unwind(payload);
+        }
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(output);
+        }
+        let Some(future) = state.future.as_mut() else {
+            // The task is never polled after completion
+            return Poll::Pending;
+        };
+        match future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                state.future = None;
+                Poll::Ready(output)
+            }
+            Poll::Pending => {
+                if !state
+                    .task_waker
+                    .as_ref()
+                    .is_some_and(|waker| waker.will_wake(cx.waker()))
+                {
+                    state.task_waker = Some(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A handle to a task spawned with [`spawn_traced`]. Works like [`tokio::task::JoinHandle`].
+pub struct TracedJoinHandle<T> {
+    handle: JoinHandle<T>,
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> TracedJoinHandle<T> {
+    /// Abort the task, see [`tokio::task::JoinHandle::abort`].
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+
+    /// Checks if the task has finished, see [`tokio::task::JoinHandle::is_finished`].
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Poll the task with a waker that captures its await points, if traces are being captured right now.
+    fn capture_task_traces(&self) {
+        if !is_capturing() {
+            return;
+        }
+        // The task is being polled on another thread, its await points are about to change anyway
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        let state = &mut *state;
+        let (Some(future), Some(task_waker)) = (state.future.as_mut(), state.task_waker.as_ref())
+        else {
+            return;
+        };
+        let Some(waker) = capturing_waker(task_waker) else {
+            return;
+        };
+        // A panic of the task must not unwind through the task that awaits it
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            future.as_mut().poll(&mut Context::from_waker(&waker))
+        })) {
+            Ok(Poll::Pending) => return,
+            Ok(Poll::Ready(output)) => state.output = Some(output),
+            Err(payload) => state.panic = Some(payload),
+        }
+        state.future = None;
+        task_waker.wake_by_ref();
+    }
+}
+
+impl<T> Future for TracedJoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.capture_task_traces();
+        Pin::new(&mut self.handle).poll(cx)
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Display},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+};
+
+use crate::filter::FrameFilter;
+
+/// A captured stack trace.
+///
+/// Unlike [`std::backtrace::Backtrace`], this gives access to individual frames.
+/// Like [`std::backtrace::Backtrace::capture`], it is only captured when `RUST_LIB_BACKTRACE`
+/// or `RUST_BACKTRACE` environment variable is set to a value other than `0`.
+///
+/// The stack is captured without resolving its symbols, which are resolved only when the trace
+/// is formatted or [`StackTrace::frames`] is called. So capturing stack traces is cheap
+/// when they are never displayed, e.g. for timeouts that are expected and handled silently.
+pub struct StackTrace {
+    frames: Option<LazyFrames>,
+}
+
+/// A single frame of a [`StackTrace`].
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StackFrame {
+    /// Demangled function name.
+    pub function: Option<String>,
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl StackTrace {
+    #[inline(never)]
+    pub(crate) fn capture(filter: &Arc<FrameFilter>) -> Self {
+        if !Self::enabled() {
+            return Self { frames: None };
+        }
+        Self {
+            frames: Some(LazyFrames::new(
+                backtrace::Backtrace::new_unresolved(),
+                filter.clone(),
+            )),
+        }
+    }
+
+    /// A stack trace of instruction pointers captured by the signal handler of the blocking detector.
+    #[cfg(all(unix, feature = "blocking-detector"))]
+    pub(crate) fn from_signal(ips: Vec<usize>) -> Self {
+        Self {
+            frames: Some(LazyFrames {
+                unresolved: Mutex::new(Some(Unresolved::Signal(ips))),
+                resolved: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Mirrors the check done by [`std::backtrace::Backtrace::capture`].
+    fn enabled() -> bool {
+        static ENABLED: OnceLock<bool> = OnceLock::new();
+        *ENABLED.get_or_init(|| {
+            std::env::var_os("RUST_LIB_BACKTRACE")
+                .or_else(|| std::env::var_os("RUST_BACKTRACE"))
+                .is_some_and(|value| value != "0")
+        })
+    }
+
+    /// Returns `false` if the stack trace was not captured because it is disabled by environment variables.
+    #[must_use]
+    pub fn is_captured(&self) -> bool {
+        self.frames.is_some()
+    }
+
+    #[cfg(all(test, feature = "tokio"))]
+    pub(crate) fn is_resolved(&self) -> bool {
+        self.frames
+            .as_ref()
+            .is_some_and(|frames| frames.resolved.get().is_some())
+    }
+
+    /// Returns the frames of the stack trace, starting from the innermost one.
+    /// Resolves the symbols on first call.
+    #[must_use]
+    pub fn frames(&self) -> &[StackFrame] {
+        self.frames
+            .as_ref()
+            .map(LazyFrames::frames)
+            .unwrap_or_default()
+    }
+}
+
+impl Display for StackTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(frames) = &self.frames else {
+            return f.write_str("disabled backtrace");
+        };
+        let frames = frames.frames();
+        for (idx, frame) in frames.iter().enumerate() {
+            writeln!(
+                f,
+                "{idx:4}: {}",
+                frame.function.as_deref().unwrap_or("<unknown>")
+            )?;
+            if let Some(file) = &frame.file {
+                write!(f, "             at {}", short_path(file).display())?;
+                if let Some(line) = frame.line {
+                    write!(f, ":{line}")?;
+                    if let Some(column) = frame.column {
+                        write!(f, ":{column}")?;
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for StackTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(frames) = &self.frames else {
+            return f.write_str("<disabled>");
+        };
+        f.write_str("Backtrace ")?;
+        f.debug_list().entries(frames.frames()).finish()
+    }
+}
+
+impl Debug for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Same format as for frames of `std::backtrace::Backtrace`
+        match &self.function {
+            Some(function) => write!(f, "{{ fn: {function:?}")?,
+            None => f.write_str("{ fn: <unknown>")?,
+        }
+        if let Some(file) = &self.file {
+            write!(f, ", file: {:?}", short_path(file))?;
+        }
+        if let Some(line) = self.line {
+            write!(f, ", line: {line}")?;
+        }
+        f.write_str(" }")
+    }
+}
+
+/// Frames captured without resolving their symbols, which is much cheaper than capturing a [`StackTrace`].
+/// The symbols are resolved and filtered on first access.
+pub(crate) struct LazyFrames {
+    unresolved: Mutex<Option<Unresolved>>,
+    resolved: OnceLock<Vec<StackFrame>>,
+}
+
+enum Unresolved {
+    /// Filtered with the filter once resolved.
+    Backtrace(backtrace::Backtrace, Arc<FrameFilter>),
+    /// Instruction pointers captured by [`crate::blocking`].
+    #[cfg(all(unix, feature = "blocking-detector"))]
+    Signal(Vec<usize>),
+}
+
+impl LazyFrames {
+    /// `unresolved` must be captured with [`backtrace::Backtrace::new_unresolved`].
+    pub(crate) fn new(unresolved: backtrace::Backtrace, filter: Arc<FrameFilter>) -> Self {
+        Self {
+            unresolved: Mutex::new(Some(Unresolved::Backtrace(unresolved, filter))),
+            resolved: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn frames(&self) -> &[StackFrame] {
+        self.resolved.get_or_init(|| {
+            let unresolved = self
+                .unresolved
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            match unresolved {
+                Some(Unresolved::Backtrace(mut backtrace, filter)) => {
+                    backtrace.resolve();
+                    filter.apply(resolved_frames(&backtrace))
+                }
+                #[cfg(all(unix, feature = "blocking-detector"))]
+                Some(Unresolved::Signal(ips)) => crate::blocking::resolve(&ips),
+                None => Vec::new(),
+            }
+        })
+    }
+}
+
+fn resolved_frames(backtrace: &backtrace::Backtrace) -> Vec<StackFrame> {
+    backtrace
+        .frames()
+        .iter()
+        .flat_map(backtrace::BacktraceFrame::symbols)
+        .map(|symbol| StackFrame {
+            function: symbol.name().map(|name| format!("{name:#}")),
+            file: symbol.filename().map(ToOwned::to_owned),
+            line: symbol.lineno(),
+            column: symbol.colno(),
+        })
+        .collect()
+}
+
+/// Shortens paths within the current directory to `./relative/path`,
+/// the same way as [`std::backtrace::Backtrace`] does.
+pub(crate) fn short_path(path: &Path) -> Cow<'_, Path> {
+    static CURRENT_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    let current_dir = CURRENT_DIR.get_or_init(|| std::env::current_dir().ok());
+    match current_dir
+        .as_deref()
+        .and_then(|current_dir| path.strip_prefix(current_dir).ok())
+    {
+        Some(relative) => Cow::Owned(Path::new(".").join(relative)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for StackTrace {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.frames())
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Wake, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::{stack::StackTrace, waker::is_capturing};
+
+/// How the inner future of a [`crate::TimeoutFuture`] was polled before it timed out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PollStats {
+    /// Number of times the inner future was polled, including the final poll that captured the traces.
+    pub count: u64,
+    /// Total time spent within `poll` of the inner future.
+    pub busy: Duration,
+    /// Time from the creation of the timeout to the last time the inner future woke its task,
+    /// or `None` if it was never woken.
+    ///
+    /// A future that was never woken is waiting for something that never happened,
+    /// while a future that was woken recently but is still pending is making slow progress or busy polling.
+    pub last_wakeup: Option<Duration>,
+}
+
+/// A poll of the inner future of a [`crate::TimeoutFuture`] that blocked the thread,
+/// detected with [`crate::TimeoutFuture::detect_blocking`].
+///
+/// While a poll blocks the thread, e.g. with synchronous IO or a long computation, the timeout can't fire
+/// and the await points are not where the time is spent. So the stack of the thread is captured by another thread
+/// while the poll is still running.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BlockedPoll {
+    /// How long the poll ran.
+    pub duration: Duration,
+    /// Stack of the blocked thread, starting from the code that was running when it was captured
+    /// and ending with the inner future of the timeout.
+    pub stack_trace: StackTrace,
+}
+
+/// Collects [`PollStats`] by timing the polls of the inner future
+/// and polling it with a waker that records wakeups.
+pub(crate) struct PollRecorder {
+    started: Instant,
+    count: u64,
+    busy: Duration,
+    wakes: Arc<WakeRecorder>,
+    /// The waker of the task that was passed to [`WakeRecorder`] and the recording waker that wraps it,
+    /// reused while the timeout is polled by the same task.
+    installed: Option<(Waker, Waker)>,
+    #[cfg(all(unix, feature = "blocking-detector"))]
+    blocking_threshold: Option<Duration>,
+    /// The longest poll that blocked the thread.
+    blocked: Option<BlockedPoll>,
+}
+
+impl PollRecorder {
+    pub(crate) fn new() -> Self {
+        let started = Instant::now();
+        Self {
+            started,
+            count: 0,
+            busy: Duration::ZERO,
+            wakes: Arc::new(WakeRecorder {
+                started,
+                last_wakeup: AtomicU64::new(NEVER_WOKEN),
+                waker: Mutex::new(None),
+            }),
+            installed: None,
+            #[cfg(all(unix, feature = "blocking-detector"))]
+            blocking_threshold: None,
+            blocked: None,
+        }
+    }
+
+    /// Capture the stack of the thread when a poll runs longer than `threshold`.
+    #[cfg(all(unix, feature = "blocking-detector"))]
+    pub(crate) fn detect_blocking(&mut self, threshold: Duration) {
+        self.blocking_threshold = Some(threshold);
+    }
+
+    /// Returns the longest poll that blocked the thread.
+    pub(crate) fn take_blocked(&mut self) -> Option<BlockedPoll> {
+        self.blocked.take()
+    }
+
+    /// Time since the creation of the timeout.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Number of polls recorded so far.
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Call `poll`, which polls the inner future, with a context that records wakeups.
+    pub(crate) fn record<R>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: impl FnOnce(&mut Context<'_>) -> R,
+    ) -> R {
+        if is_capturing() {
+            // An enclosing timeout is capturing traces, its waker must reach the await points unwrapped
+            return self.time(|| poll(cx));
+        }
+        let installed = match self.installed.take() {
+            Some(installed) if installed.0.will_wake(cx.waker()) => installed,
+            _ => {
+                // The timeout is polled for the first time or moved to another task
+                *self.wakes.lock() = Some(cx.waker().clone());
+                (cx.waker().clone(), Waker::from(self.wakes.clone()))
+            }
+        };
+        let result = self.time(|| poll(&mut Context::from_waker(&installed.1)));
+        self.installed = Some(installed);
+        result
+    }
+
+    fn time<R>(&mut self, poll: impl FnOnce() -> R) -> R {
+        #[cfg(all(unix, feature = "blocking-detector"))]
+        let watch = self.blocking_threshold.map(crate::blocking::watch);
+        let start = Instant::now();
+        let result = poll();
+        self.count += 1;
+        self.busy += start.elapsed();
+        #[cfg(all(unix, feature = "blocking-detector"))]
+        if let Some(blocked) = watch.and_then(crate::blocking::WatchGuard::finish)
+            && self
+                .blocked
+                .as_ref()
+                .is_none_or(|longest| longest.duration < blocked.duration)
+        {
+            self.blocked = Some(blocked);
+        }
+        result
+    }
+
+    pub(crate) fn stats(&self) -> PollStats {
+        let last_wakeup = self.wakes.last_wakeup.load(Ordering::Relaxed);
+        PollStats {
+            count: self.count,
+            busy: self.busy,
+            last_wakeup: (last_wakeup != NEVER_WOKEN).then(|| Duration::from_nanos(last_wakeup)),
+        }
+    }
+}
+
+/// Value of [`WakeRecorder::last_wakeup`] before the first wakeup.
+const NEVER_WOKEN: u64 = u64::MAX;
+
+struct WakeRecorder {
+    started: Instant,
+    /// Nanoseconds from `started` to the last wakeup.
+    last_wakeup: AtomicU64,
+    /// The waker of the task that polls the timeout
+    waker: Mutex<Option<Waker>>,
+}
+
+impl WakeRecorder {
+    fn lock(&self) -> MutexGuard<'_, Option<Waker>> {
+        self.waker.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Wake for WakeRecorder {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let elapsed = u64::try_from(self.started.elapsed().as_nanos()).unwrap_or(NEVER_WOKEN - 1);
+        self.last_wakeup.store(elapsed, Ordering::Relaxed);
+        let waker = self.lock().clone();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
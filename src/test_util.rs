@@ -0,0 +1,51 @@
+use std::{fmt::Display, time::Duration};
+
+use tracing::subscriber::NoSubscriber;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{timeout, trace::CaptureTrace};
+
+/// Run a test body with a time limit, panicking with the formatted [`crate::TimeoutElapsed`] if it hangs.
+///
+/// Unless a [`tracing`] subscriber is already set, a subscriber with [`ErrorLayer`] is installed
+/// for the current thread, so that span traces are captured. An existing subscriber
+/// must include [`ErrorLayer`] for span traces to be captured.
+///
+/// This is what `#[timeout_tracing::test]` expands to.
+///
+/// # Panics
+///
+/// Panics if `fut` does not complete within `duration`.
+///
+/// # Examples
+/// ```rust,should_panic
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, run_test};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// run_test(Duration::from_millis(10), CaptureSpanTrace, async {
+///     sleep(Duration::from_secs(1)).await;
+/// })
+/// .await;
+/// # });
+/// ```
+pub async fn run_test<C, Fut>(duration: Duration, capture: C, fut: Fut) -> Fut::Output
+where
+    C: CaptureTrace + Send + 'static,
+    C::Trace: Display,
+    Fut: Future,
+{
+    let has_subscriber =
+        tracing::dispatcher::get_default(|dispatch| !dispatch.is::<NoSubscriber>());
+    let _guard = (!has_subscriber).then(|| {
+        let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+        tracing::subscriber::set_default(subscriber)
+    });
+    match timeout(duration, capture, fut).await {
+        Ok(output) => output,
+        Err(elapsed) => panic!("test {elapsed}"),
+    }
+}
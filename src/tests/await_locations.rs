@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::{join, time::sleep};
+
+use crate::{CaptureAwaitLocations, TimeoutElapsed, tests::insta_trace_filters, timeout};
+
+#[tokio::test]
+#[serial]
+async fn with_uninstrumented_functions() {
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureAwaitLocations::new(),
+        do_join(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert_eq!(err.active_traces.len(), 2);
+    // Only frames of this crate are kept, without the capture frames and the frames of tokio and std
+    for trace in &err.active_traces {
+        assert!(trace.locations().iter().all(|frame| {
+            frame
+                .function
+                .as_deref()
+                .unwrap()
+                .starts_with("timeout_tracing::tests::await_locations::")
+        }));
+    }
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+async fn do_join() {
+    join!(do_sleep(), do_nested_sleep());
+}
+
+async fn do_nested_sleep() {
+    do_sleep().await;
+}
+
+async fn do_sleep() {
+    sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::{join, time::sleep};
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    TimeoutElapsed,
+    tests::{insta_trace_filters, run_with_tracing},
+    timeout,
+    trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_span_trace() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = timeout(Duration::from_millis(100), CaptureSpanTrace, do_join()).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert_eq!(err.active_traces.len(), 6);
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err.await_tree());
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_stack_and_span_trace() {
+    let result = run_with_tracing(Duration::from_millis(100), do_join()).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err.await_tree());
+    });
+}
+
+#[instrument]
+async fn do_join() {
+    join!(
+        do_sleep(1),
+        do_sleep(2),
+        do_nested_join(3),
+        do_sleep_twice()
+    );
+}
+
+#[instrument]
+async fn do_nested_join(n: i32) {
+    join!(do_sleep(n * 10), do_sleep(n * 10 + 1));
+}
+
+#[instrument]
+async fn do_sleep_twice() {
+    join!(sleep(Duration::from_secs(1)), sleep(Duration::from_secs(1)));
+}
+
+#[instrument]
+async fn do_sleep(n: i32) {
+    sleep(Duration::from_secs(1)).await;
+}
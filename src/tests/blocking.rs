@@ -0,0 +1,163 @@
+use std::{
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use serial_test::serial;
+use tokio::time::sleep;
+
+use crate::{TimeoutFuture, timer::DefaultTimer, trace::CaptureSpanTrace};
+
+#[tokio::test]
+#[serial]
+async fn blocked_in_poll() {
+    let result = TimeoutFuture::<_, _, DefaultTimer>::new(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        blocking_task(),
+    )
+    .detect_blocking(Duration::from_millis(20))
+    .await;
+
+    let err = result.err().unwrap();
+    let blocked = err.blocked_in_poll.as_ref().unwrap();
+    assert!(blocked.duration >= Duration::from_millis(200));
+    let functions: Vec<_> = blocked
+        .stack_trace
+        .frames()
+        .iter()
+        .filter_map(|frame| frame.function.as_deref())
+        .collect();
+    assert!(
+        functions
+            .iter()
+            .any(|function| function.contains("block_thread")),
+        "{functions:#?}"
+    );
+    assert!(
+        !functions
+            .iter()
+            .any(|function| function.contains("timeout_tracing::blocking")
+                || function.contains("TimeoutFuture")),
+        "{functions:#?}"
+    );
+    assert!(err.to_string().starts_with("thread blocked in poll for"));
+}
+
+#[tokio::test]
+#[serial]
+async fn blocked_while_capturing_backtrace() {
+    let result = TimeoutFuture::<_, _, DefaultTimer>::new(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        capturing_task(),
+    )
+    .detect_blocking(Duration::from_millis(20))
+    .await;
+
+    // The thread is interrupted while it is unwinding its own stack, the handler must still capture it
+    let err = result.err().unwrap();
+    let blocked = err.blocked_in_poll.as_ref().unwrap();
+    assert!(blocked.duration >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+#[serial]
+async fn pending_without_blocking() {
+    let result = TimeoutFuture::<_, _, DefaultTimer>::new(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        sleep(Duration::from_secs(1)),
+    )
+    .detect_blocking(Duration::from_millis(20))
+    .await;
+
+    assert!(result.err().unwrap().blocked_in_poll.is_none());
+}
+
+/// Set in the process that runs [`previous_handler_still_called`] with a fresh detector.
+const PREVIOUS_HANDLER_ENV: &str = "TIMEOUT_TRACING_TEST_PREVIOUS_HANDLER";
+
+#[test]
+fn previous_handler_still_called() {
+    // The detector installs its handler once per process, so the test needs a process of its own
+    if std::env::var_os(PREVIOUS_HANDLER_ENV).is_none() {
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::blocking::previous_handler_still_called"])
+            .env(PREVIOUS_HANDLER_ENV, "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        return;
+    }
+
+    // SAFETY: all zeroes is a valid `sigaction`
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = count_call as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: `count_call` only uses an atomic
+    let installed =
+        unsafe { libc::sigaction(libc::SIGURG, &raw const action, std::ptr::null_mut()) };
+    assert_eq!(installed, 0);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let result = runtime.block_on(async {
+        TimeoutFuture::<_, _, DefaultTimer>::new(
+            Duration::from_millis(50),
+            CaptureSpanTrace,
+            blocking_task(),
+        )
+        .detect_blocking(Duration::from_millis(20))
+        .await
+    });
+    assert!(result.err().unwrap().blocked_in_poll.is_some());
+    // The signals of the detector are not passed on
+    assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+
+    // SAFETY: `getpid` is always safe to call
+    let process = unsafe { libc::getpid() };
+    // SAFETY: the signal is handled by the detector, which passes it on to `count_call`.
+    // It is sent to the process with `kill`, unlike the signals of the detector
+    let sent = unsafe { libc::kill(process, libc::SIGURG) };
+    assert_eq!(sent, 0);
+    let waiting_since = Instant::now();
+    while CALLS.load(Ordering::Relaxed) == 0 {
+        assert!(waiting_since.elapsed() < Duration::from_secs(1));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Calls of [`count_call`], the `SIGURG` handler installed by [`previous_handler_still_called`].
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn count_call(_signal: libc::c_int) {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+async fn blocking_task() {
+    sleep(Duration::from_millis(10)).await;
+    block_thread();
+    sleep(Duration::from_secs(1)).await;
+}
+
+async fn capturing_task() {
+    sleep(Duration::from_millis(10)).await;
+    capture_backtraces();
+    sleep(Duration::from_secs(1)).await;
+}
+
+#[inline(never)]
+fn capture_backtraces() {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(200) {
+        backtrace::trace(|_| true);
+    }
+}
+
+#[inline(never)]
+fn block_thread() {
+    std::thread::sleep(Duration::from_millis(200));
+}
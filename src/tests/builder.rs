@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    Timeout, TimeoutElapsed, tests::insta_trace_filters, timeout, trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn label_and_fields() {
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(ErrorLayer::default()),
+    );
+    let result = Timeout::new(Duration::from_millis(50))
+        .label("fetch_user")
+        .field("user_id", 42)
+        .field("region", "eu")
+        .run(fetch_user())
+        .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert_eq!(err.label.as_deref(), Some("fetch_user"));
+    assert_eq!(
+        err.fields,
+        [("user_id", "42".to_owned()), ("region", "eu".to_owned())]
+    );
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn fields_on_deadline_span() {
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(ErrorLayer::default()),
+    );
+    let result = timeout(Duration::from_millis(50), CaptureSpanTrace, async {
+        Timeout::new(Duration::from_secs(1))
+            .label("fetch_user")
+            .field("user_id", 42)
+            .run(fetch_user())
+            .await
+    })
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let mut err = result.err().unwrap();
+    err.active_traces.sort_by_cached_key(ToString::to_string);
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+#[serial]
+async fn serialize_label_and_fields() {
+    let result = Timeout::new(Duration::from_millis(10))
+        .label("fetch_user")
+        .field("user_id", 42)
+        .run(fetch_user())
+        .await;
+
+    let json = serde_json::to_value(result.err().unwrap()).unwrap();
+    assert_eq!(json["label"], "fetch_user");
+    assert_eq!(json["fields"], serde_json::json!({ "user_id": "42" }));
+}
+
+#[instrument]
+async fn fetch_user() {
+    sleep(Duration::from_secs(2)).await;
+}
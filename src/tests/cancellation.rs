@@ -0,0 +1,116 @@
+use std::{
+    pin::pin,
+    sync::{Arc, Weak},
+    task::{Context, Wake, Waker},
+    time::Duration,
+};
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    CancellationToken, TimeoutElapsed, tests::insta_trace_filters, timeout, trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn finished_during_grace_period() {
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(ErrorLayer::default()),
+    );
+    let token = CancellationToken::new();
+    let result = timeout(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        transaction(token.clone(), Duration::from_millis(1)),
+    )
+    .grace_period(token.clone(), Duration::from_secs(1))
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert!(token.is_cancelled());
+    // The sleep and the token
+    assert_eq!(err.active_traces.len(), 2);
+    assert!(err.stuck_during_cancellation.is_none());
+}
+
+#[tokio::test]
+#[serial]
+async fn stuck_during_cancellation() {
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(ErrorLayer::default()),
+    );
+    let token = CancellationToken::new();
+    let result = timeout(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        transaction(token.clone(), Duration::from_secs(1)),
+    )
+    .grace_period(token, Duration::from_millis(50))
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert_eq!(err.stuck_during_cancellation.as_ref().unwrap().len(), 1);
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn finished_before_deadline() {
+    let token = CancellationToken::new();
+    let result = timeout(Duration::from_secs(1), CaptureSpanTrace, async { 42 })
+        .grace_period(token.clone(), Duration::from_secs(1))
+        .await;
+
+    assert_eq!(result.unwrap(), 42);
+    assert!(!token.is_cancelled());
+}
+
+#[test]
+fn dropped_waiters_release_their_wakers() {
+    struct NoopWake;
+
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let token = CancellationToken::new();
+    let mut tasks = Vec::new();
+    for _ in 0..100 {
+        // A different task each time, e.g. a `select!` in a loop spawned per request
+        let task = Arc::new(NoopWake);
+        tasks.push(Arc::downgrade(&task));
+        let waker = Waker::from(task);
+        let mut cancelled = pin!(token.cancelled());
+        assert!(
+            cancelled
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending()
+        );
+    }
+
+    assert!(tasks.iter().all(|task| Weak::upgrade(task).is_none()));
+}
+
+#[instrument(skip(token))]
+async fn transaction(token: CancellationToken, rollback_duration: Duration) {
+    tokio::select! {
+        () = sleep(Duration::from_secs(1)) => {}
+        () = token.cancelled() => rollback(rollback_duration).await,
+    }
+}
+
+#[instrument]
+async fn rollback(duration: Duration) {
+    sleep(duration).await;
+}
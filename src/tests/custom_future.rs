@@ -1,4 +1,8 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    task::Waker,
+    time::Duration,
+};
 
 use pin_project_lite::pin_project;
 use serial_test::serial;
@@ -60,3 +64,43 @@ impl Future for CustomFut {
         self.project().inner.poll(cx)
     }
 }
+
+#[tokio::test]
+#[serial]
+async fn with_wakers_kept_after_timeout() {
+    let wakers = Arc::new(Mutex::new(Vec::new()));
+    let result = run_with_tracing(
+        Duration::from_millis(10),
+        KeepWakers {
+            wakers: wakers.clone(),
+        },
+    )
+    .await;
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+
+    // The traces were taken by the timeout, but the wakers captured them live on
+    let old = std::mem::take(&mut *wakers.lock().unwrap());
+    assert_eq!(old.len(), 4);
+    let new = old[3].clone();
+    drop(old);
+    drop(new);
+}
+
+/// Keeps every waker it is polled with, e.g. as a channel shared with another task would.
+struct KeepWakers {
+    wakers: Arc<Mutex<Vec<Waker>>>,
+}
+
+impl Future for KeepWakers {
+    type Output = ();
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let mut wakers = self.wakers.lock().unwrap();
+        wakers.push(cx.waker().clone());
+        wakers.push(cx.waker().clone());
+        std::task::Poll::Pending
+    }
+}
@@ -0,0 +1,137 @@
+use std::{future::poll_fn, pin::pin, task::Poll, time::Duration};
+
+use serial_test::serial;
+use tokio::time::{Instant, sleep};
+
+use crate::{Limit, remaining, timeout, trace::CaptureSpanTrace};
+
+#[tokio::test]
+#[serial]
+async fn inner_timeout_inherits_deadline() {
+    let result = timeout(Duration::from_millis(50), CaptureSpanTrace, async {
+        let remaining = remaining().unwrap();
+        assert!(remaining <= Duration::from_millis(50));
+        timeout(
+            Duration::from_secs(10),
+            CaptureSpanTrace,
+            sleep(Duration::from_secs(1)),
+        )
+        .await
+    })
+    .propagate_deadline()
+    .await;
+
+    // Both timers elapse at the same time, and the outer timeout sees its own deadline first.
+    // The error of the nested timeout is returned because it completes in the final poll
+    // that the outer timeout does to capture the traces, not because its timer fires first.
+    let err = result.unwrap().err().unwrap();
+    assert_eq!(err.limit, Limit::Inherited);
+    assert_eq!(err.duration, Duration::from_secs(10));
+    assert!(err.elapsed < Duration::from_secs(1));
+    assert!(err.to_string().starts_with(
+        "the deadline inherited from an enclosing timeout elapsed before the 10s limit"
+    ));
+}
+
+#[tokio::test]
+#[serial]
+async fn tighter_local_deadline() {
+    let result = timeout(Duration::from_secs(10), CaptureSpanTrace, async {
+        timeout(
+            Duration::from_millis(10),
+            CaptureSpanTrace,
+            sleep(Duration::from_secs(1)),
+        )
+        .await
+    })
+    .propagate_deadline()
+    .await;
+
+    let err = result.unwrap().err().unwrap();
+    assert_eq!(err.limit, Limit::Local);
+}
+
+#[tokio::test]
+#[serial]
+async fn follows_earlier_reset_of_enclosing_timeout() {
+    let mut outer = pin!(
+        timeout(
+            Duration::from_secs(10),
+            CaptureSpanTrace,
+            timeout(
+                Duration::from_secs(5),
+                CaptureSpanTrace,
+                sleep(Duration::from_secs(1)),
+            ),
+        )
+        .propagate_deadline()
+    );
+    assert!(poll_once(outer.as_mut()).await.is_pending());
+    outer
+        .as_mut()
+        .reset(Instant::now() + Duration::from_millis(50));
+
+    let err = outer.await.unwrap().err().unwrap();
+    assert_eq!(err.limit, Limit::Inherited);
+    assert!(err.elapsed < Duration::from_secs(1));
+}
+
+#[tokio::test]
+#[serial]
+async fn follows_later_reset_of_enclosing_timeout() {
+    let mut outer = pin!(
+        timeout(
+            Duration::from_millis(50),
+            CaptureSpanTrace,
+            timeout(
+                Duration::from_secs(5),
+                CaptureSpanTrace,
+                sleep(Duration::from_millis(100)),
+            ),
+        )
+        .propagate_deadline()
+    );
+    assert!(poll_once(outer.as_mut()).await.is_pending());
+    outer
+        .as_mut()
+        .reset(Instant::now() + Duration::from_secs(10));
+
+    // The nested timeout is back to its own deadline
+    assert!(outer.await.unwrap().is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+#[serial]
+async fn measured_with_timer_clock() {
+    let result = timeout(Duration::from_secs(1), CaptureSpanTrace, async {
+        sleep(Duration::from_millis(400)).await;
+        // Time does not pass while the clock is paused, unlike the time of the system clock
+        std::thread::sleep(Duration::from_millis(10));
+        remaining()
+    })
+    .propagate_deadline()
+    .await;
+
+    assert_eq!(result.unwrap(), Some(Duration::from_millis(600)));
+}
+
+async fn poll_once<Fut: Future>(mut fut: std::pin::Pin<&mut Fut>) -> Poll<Fut::Output> {
+    poll_fn(|cx| Poll::Ready(fut.as_mut().poll(cx))).await
+}
+
+#[tokio::test]
+#[serial]
+async fn not_propagated_by_default() {
+    let result = timeout(Duration::from_millis(50), CaptureSpanTrace, async {
+        assert_eq!(remaining(), None);
+        timeout(
+            Duration::from_secs(10),
+            CaptureSpanTrace,
+            sleep(Duration::from_secs(1)),
+        )
+        .await
+    })
+    .await;
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::time::{Instant, sleep};
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    TimeoutElapsed, TimeoutTracingExt, tests::insta_trace_filters, trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_timeout_traced() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = do_sleep()
+        .timeout_traced_default(Duration::from_millis(100))
+        .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_timeout_traced_at() {
+    let result = sleep(Duration::from_millis(10))
+        .timeout_traced_at(Instant::now() + Duration::from_secs(1), CaptureSpanTrace)
+        .await;
+
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+#[serial]
+async fn with_timeout_traced_chain() {
+    let result = async { 42 }
+        .timeout_traced(Duration::from_secs(1), CaptureSpanTrace)
+        .await;
+
+    assert_eq!(result.ok(), Some(42));
+}
+
+#[instrument]
+async fn do_sleep() {
+    sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+
+use crate::{
+    FrameFilter, StackFrame, TimeoutElapsed,
+    tests::{insta_trace_filters, run_with_capture},
+    trace::CaptureSpanAndStackTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_allowed_crate() {
+    let capture =
+        CaptureSpanAndStackTrace.with_filter(FrameFilter::new().allow_crate("timeout_tracing"));
+    let result = run_with_capture(Duration::from_millis(100), capture, do_sleep()).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert_eq!(err.active_traces.len(), 1);
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err.active_traces[0].stack_trace());
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_denied_crates() {
+    let capture = CaptureSpanAndStackTrace
+        .with_filter(FrameFilter::new().deny_crate("tokio").deny_crate("tracing"));
+    let result = run_with_capture(Duration::from_millis(100), capture, do_sleep()).await;
+
+    let err = result.err().unwrap();
+    let frames = err.active_traces[0].stack_trace().frames();
+    assert!(!frames.is_empty());
+    assert!(
+        !frames
+            .iter()
+            .any(|frame| function(frame).contains("tokio::"))
+    );
+    assert!(
+        !frames
+            .iter()
+            .any(|frame| function(frame).starts_with("<tracing::"))
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn with_all_frames() {
+    let capture = CaptureSpanAndStackTrace.with_filter(FrameFilter::keep_all());
+    let result = run_with_capture(Duration::from_millis(100), capture, do_sleep()).await;
+
+    let err = result.err().unwrap();
+    let frames = err.active_traces[0].stack_trace().frames();
+    assert!(function(&frames[0]).starts_with("timeout_tracing::stack::StackTrace::capture"));
+    assert!(
+        frames
+            .iter()
+            .any(|frame| function(frame).starts_with("timeout_tracing::waker::poll_with_capture"))
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn resolves_symbols_lazily() {
+    let result = run_with_capture(
+        Duration::from_millis(100),
+        CaptureSpanAndStackTrace,
+        do_sleep(),
+    )
+    .await;
+
+    let err = result.err().unwrap();
+    let stack_trace = err.active_traces[0].stack_trace();
+    assert!(stack_trace.is_captured());
+    assert!(!stack_trace.is_resolved());
+    assert!(!stack_trace.frames().is_empty());
+    assert!(stack_trace.is_resolved());
+}
+
+fn function(frame: &StackFrame) -> &str {
+    frame.function.as_deref().unwrap_or_default()
+}
+
+#[instrument]
+async fn do_sleep() {
+    sleep(Duration::from_secs(1)).await;
+}
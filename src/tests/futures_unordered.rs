@@ -1,13 +1,17 @@
 use std::time::Duration;
 
-use futures::{StreamExt, stream::FuturesUnordered};
+use futures::{StreamExt, stream, stream::FuturesUnordered};
 use serial_test::serial;
 use tokio::time::sleep;
 use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
 
 use crate::{
-    TimeoutElapsed,
+    TimeoutElapsed, TimeoutTracingExt,
     tests::{insta_trace_filters, run_with_tracing},
+    timeout,
+    trace::CaptureSpanTrace,
 };
 
 #[tokio::test]
@@ -28,6 +32,70 @@ async fn with_futures_unordered() {
     });
 }
 
+#[tokio::test]
+#[serial]
+async fn with_forward_capture() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace,
+        do_unordered_forward(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let mut err = result.err().unwrap();
+    // One trace for each child and one for `buffer_unordered` itself
+    assert_eq!(err.active_traces.len(), 11);
+    // The order in which `buffer_unordered` polls its children is not specified
+    err.active_traces.sort_by_cached_key(ToString::to_string);
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err.await_tree());
+    });
+}
+
+#[cfg(feature = "futures")]
+#[tokio::test]
+#[serial]
+async fn with_forward_capture_streams() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace,
+        do_select_all_forward(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    assert_eq!(err.await_tree().roots[0].children.len(), 3);
+}
+
+#[instrument]
+async fn do_unordered_forward() {
+    let mut buffered = stream::iter(0..10)
+        .map(|i| inner_fut(i).forward_capture())
+        .buffer_unordered(10);
+
+    while let Some(()) = buffered.next().await {}
+}
+
+#[cfg(feature = "futures")]
+#[instrument]
+async fn do_select_all_forward() {
+    let mut streams = stream::select_all(
+        (0..3).map(|i| crate::forward_capture(stream::once(inner_fut(i)).boxed())),
+    );
+
+    while let Some(()) = streams.next().await {}
+}
+
 #[instrument]
 async fn do_unordered() {
     let mut fu = FuturesUnordered::new();
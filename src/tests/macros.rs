@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing_error::{ErrorLayer, SpanTrace};
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    CaptureSpanAndStackTrace, StackAndSpanTrace, TimeoutElapsed, tests::insta_trace_filters,
+    traced_timeout,
+};
+
+#[derive(Debug)]
+enum Error {
+    Timeout(TimeoutElapsed<SpanTrace>),
+}
+
+impl From<TimeoutElapsed<SpanTrace>> for Error {
+    fn from(value: TimeoutElapsed<SpanTrace>) -> Self {
+        Self::Timeout(value)
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn with_traced_timeout() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    assert_eq!(do_sleep(1).await.unwrap(), 1);
+    let Err(Error::Timeout(err)) = do_sleep(1000).await else {
+        panic!("expected a timeout");
+    };
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_custom_capture() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let err = do_sleep_with_stack(1000).await.unwrap_err();
+    assert_eq!(err.active_traces.len(), 1);
+    do_sleep_with_stack(1).await.unwrap();
+    assert_eq!(do_early_return().await.unwrap(), 42);
+}
+
+#[traced_timeout(duration = "100ms", instrument)]
+async fn do_sleep(delay: u64) -> Result<u64, Error> {
+    sleep(Duration::from_millis(delay)).await;
+    Ok(delay)
+}
+
+#[traced_timeout(duration = "100ms", capture = CaptureSpanAndStackTrace)]
+async fn do_sleep_with_stack(delay: u64) -> Result<(), TimeoutElapsed<StackAndSpanTrace>> {
+    sleep(Duration::from_millis(delay)).await;
+    Ok(())
+}
+
+#[traced_timeout(duration = "1m30s", instrument(skip_all))]
+async fn do_early_return() -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let value: u64 = "42".parse()?;
+    if value == 42 {
+        return Ok(value);
+    }
+    unreachable!()
+}
@@ -7,20 +7,47 @@ use tracing_subscriber::layer::SubscriberExt;
 
 use crate::{
     TimeoutElapsed,
-    trace::{CaptureSpanAndStackTrace, StackAndSpanTrace},
+    trace::{CaptureSpanAndStackTrace, CaptureTrace, StackAndSpanTrace},
 };
 
+mod await_locations;
+mod await_tree;
+#[cfg(all(feature = "blocking-detector", unix))]
+mod blocking;
+mod builder;
+mod cancellation;
 mod custom_future;
+mod deadline_propagation;
+mod ext;
 mod format_values;
+mod frame_filter;
 mod futures_select;
 mod futures_unordered;
 mod join;
+#[cfg(feature = "macros")]
+mod macros;
 mod nested_tracing_timeout;
+mod on_drop;
+mod poll_stats;
+mod registry;
 mod reqwest;
+mod resource;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(all(feature = "signal", unix))]
+mod signal;
 mod sleep;
 mod span_trace;
+mod spawn;
 mod sqlx;
+#[cfg(all(feature = "macros", feature = "test-util"))]
+mod test_macro;
+mod timeout_at;
+mod timers;
 mod tokio_select;
+#[cfg(feature = "tower")]
+mod tower;
+mod watchdog;
 
 async fn run_with_tracing<Fut>(
     duration: Duration,
@@ -29,6 +56,19 @@ async fn run_with_tracing<Fut>(
 where
     Fut: Future,
     Fut::Output: Debug,
+{
+    run_with_capture(duration, CaptureSpanAndStackTrace, f).await
+}
+
+async fn run_with_capture<C, Fut>(
+    duration: Duration,
+    capture: C,
+    f: Fut,
+) -> Result<Fut::Output, TimeoutElapsed<StackAndSpanTrace>>
+where
+    C: CaptureTrace<Trace = StackAndSpanTrace> + Send + 'static,
+    Fut: Future,
+    Fut::Output: Debug,
 {
     let subscriber = tracing_subscriber::registry()
         .with(ErrorLayer::default())
@@ -43,7 +83,7 @@ where
     unsafe { std::env::set_var("RUST_BACKTRACE", "1") };
 
     info!("before call");
-    let result = crate::timeout(duration, CaptureSpanAndStackTrace, f).await;
+    let result = crate::timeout(duration, capture, f).await;
     info!("after call");
 
     match old_val {
@@ -93,7 +133,20 @@ fn insta_trace_filters() -> Vec<(&'static str, &'static str)> {
             r"at [crates]/$1-[ver]/",
         ),
         (r"line: [0-9]+", r"line: [NNN]"),
+        (r#""line": [0-9]+"#, r#""line": [NNN]"#),
         (r"\.rs:[0-9]+:[0-9]+", r".rs:[NNN]:[NNN]"),
         (r"\.rs:[0-9]+", r".rs:[NNN]"),
+        (
+            r"(elapsed|busy|remaining): [0-9.]+[a-zµ]+",
+            r"$1: [DURATION]",
+        ),
+        (r"remaining=[0-9.]+[a-zµ]+", r"remaining=[DURATION]"),
+        (r", [0-9.]+[a-zµ]+ remaining", r", [DURATION] remaining"),
+        (r"(dropped after) [0-9.]+[a-zµ]+", r"$1 [DURATION]"),
+        (r"(last_wakeup: Some\()[^)]*\)", r"$1[DURATION])"),
+        (
+            r#""(elapsed|busy|last_wakeup|remaining)": \{\s*"secs": [0-9]+,\s*"nanos": [0-9]+\s*\}"#,
+            r#""$1": [DURATION]"#,
+        ),
     ]
 }
@@ -28,6 +28,31 @@ async fn with_timeouts() {
     });
 }
 
+#[tokio::test]
+#[serial]
+async fn inner_elapsed() {
+    let result = run_with_tracing(Duration::from_secs(1), async {
+        timeout(Duration::from_millis(50), CaptureSpanAndStackTrace, do_g())
+            .label("do_g")
+            .await
+    })
+    .await;
+
+    let err = result.unwrap().err().unwrap();
+    assert!(err.is_inner());
+    assert_eq!(err.deadlines.len(), 2);
+    assert_eq!(err.deadlines[0].label, None);
+    assert_eq!(err.deadlines[0].duration, Duration::from_secs(1));
+    assert!(err.deadlines[0].remaining > Duration::ZERO);
+    assert_eq!(err.deadlines[1].label.as_deref(), Some("do_g"));
+    assert_eq!(err.deadlines[1].remaining, Duration::ZERO);
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
 #[instrument]
 async fn do_f() {
     _ = timeout(Duration::from_secs(1), CaptureSpanAndStackTrace, do_g()).await;
@@ -0,0 +1,134 @@
+use std::{
+    future::poll_fn,
+    panic::{self, AssertUnwindSafe},
+    pin::pin,
+    sync::mpsc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{tests::insta_trace_filters, timeout, trace::CaptureSpanTrace};
+
+#[tokio::test]
+#[serial]
+async fn dropped_by_select() {
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(ErrorLayer::default()),
+    );
+    let (sender, receiver) = mpsc::channel();
+    let fut = timeout(Duration::from_secs(1), CaptureSpanTrace, slow_query())
+        .on_drop(move |report| sender.send(report).unwrap());
+    tokio::select! {
+        _ = fut => unreachable!(),
+        () = sleep(Duration::from_millis(50)) => {}
+    }
+
+    let report = receiver.try_recv().unwrap();
+    assert!(report.elapsed >= Duration::from_millis(50));
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(report);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn not_called_after_completion() {
+    let (sender, receiver) = mpsc::channel::<()>();
+    let completed = timeout(Duration::from_secs(1), CaptureSpanTrace, async {})
+        .on_drop(move |_| sender.send(()).unwrap())
+        .await;
+    let elapsed = timeout(Duration::from_millis(10), CaptureSpanTrace, slow_query())
+        .on_drop(|_| panic!("the timeout has elapsed"))
+        .await;
+
+    assert!(completed.is_ok());
+    assert!(elapsed.is_err());
+    assert!(receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn not_polled_if_never_polled() {
+    let (sender, receiver) = mpsc::channel();
+    let fut = timeout(
+        Duration::from_secs(1),
+        CaptureSpanTrace,
+        poll_fn(|_| -> Poll<()> { panic!("the future was started in the drop") }),
+    )
+    .on_drop(move |report| sender.send(report).unwrap());
+    drop(fut);
+
+    assert!(receiver.try_recv().unwrap().active_traces.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn panic_in_last_poll() {
+    let (sender, receiver) = mpsc::channel();
+    let mut polls = 0;
+    {
+        let mut fut = pin!(
+            timeout(
+                Duration::from_secs(1),
+                CaptureSpanTrace,
+                poll_fn(move |_| {
+                    polls += 1;
+                    assert_eq!(polls, 1, "the future panicked");
+                    Poll::<()>::Pending
+                }),
+            )
+            .on_drop(move |report| sender.send(report).unwrap())
+        );
+        assert!(
+            fut.as_mut()
+                .poll(&mut Context::from_waker(Waker::noop()))
+                .is_pending()
+        );
+    }
+
+    // The panic does not unwind out of the drop
+    assert!(receiver.try_recv().unwrap().active_traces.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn not_polled_while_panicking() {
+    let (sender, receiver) = mpsc::channel();
+    let (polled, polls) = mpsc::channel();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut fut = pin!(
+            timeout(
+                Duration::from_secs(1),
+                CaptureSpanTrace,
+                poll_fn(move |_| {
+                    polled.send(()).unwrap();
+                    Poll::<()>::Pending
+                }),
+            )
+            .on_drop(move |report| sender.send(report).unwrap())
+        );
+        assert!(
+            fut.as_mut()
+                .poll(&mut Context::from_waker(Waker::noop()))
+                .is_pending()
+        );
+        panic!("the caller panicked");
+    }));
+
+    assert!(result.is_err());
+    assert_eq!(polls.try_iter().count(), 1);
+    assert!(receiver.try_recv().unwrap().active_traces.is_empty());
+}
+
+#[instrument]
+async fn slow_query() {
+    sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::{task::yield_now, time::sleep};
+
+use crate::{timeout, trace::CaptureSpanTrace};
+
+#[tokio::test]
+#[serial]
+async fn never_woken() {
+    let result = timeout(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        std::future::pending::<()>(),
+    )
+    .await;
+
+    let err = result.err().unwrap();
+    assert_eq!(err.duration, Duration::from_millis(50));
+    assert!(err.elapsed >= Duration::from_millis(50));
+    // The first poll and the final poll that captured the traces
+    assert_eq!(err.polls.count, 2);
+    assert!(err.polls.busy < err.elapsed);
+    assert_eq!(err.polls.last_wakeup, None);
+}
+
+#[tokio::test]
+#[serial]
+async fn busy_polling() {
+    let result = timeout(Duration::from_millis(50), CaptureSpanTrace, async {
+        loop {
+            yield_now().await;
+        }
+    })
+    .await;
+
+    let err = result.err().unwrap();
+    assert!(err.polls.count > 10);
+    let last_wakeup = err.polls.last_wakeup.unwrap();
+    let since_last_wakeup = err.elapsed.checked_sub(last_wakeup).unwrap();
+    assert!(since_last_wakeup < Duration::from_millis(50));
+}
+
+#[tokio::test]
+#[serial]
+async fn woken_once() {
+    let result = timeout(Duration::from_millis(100), CaptureSpanTrace, async {
+        sleep(Duration::from_millis(10)).await;
+        sleep(Duration::from_secs(1)).await;
+    })
+    .await;
+
+    let err = result.err().unwrap();
+    assert_eq!(err.polls.count, 3);
+    assert!(err.polls.last_wakeup.unwrap() >= Duration::from_millis(10));
+}
@@ -0,0 +1,131 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    task::Poll,
+    time::Duration,
+};
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    dump_all, dump_all_within, tests::insta_trace_filters, timeout, trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_span_trace() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let slow = tokio::spawn(
+        timeout(Duration::from_secs(10), CaptureSpanTrace, do_sleep(1)).register("slow request"),
+    );
+    let fast = tokio::spawn(
+        timeout(Duration::from_secs(10), CaptureSpanTrace, async {}).register("fast request"),
+    );
+    fast.await.unwrap().unwrap();
+    sleep(Duration::from_millis(10)).await;
+
+    let dumps = dump_all().await;
+
+    // Completed futures are not reported
+    assert_eq!(dumps.len(), 1);
+    let dump = &dumps[0];
+    assert_eq!(dump.label, "slow request");
+    assert!(dump.remaining.unwrap() > Duration::from_secs(9));
+    assert!(dump.deadline.unwrap() > dump.started);
+    let traces = dump.active_traces.as_ref().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(traces.join("\n"));
+    });
+
+    // The future keeps running after its traces are captured
+    slow.abort();
+    assert!(slow.await.unwrap_err().is_cancelled());
+    assert!(dump_all().await.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn with_blocked_future() {
+    let future =
+        timeout(Duration::from_secs(10), CaptureSpanTrace, do_sleep(2)).register("blocked");
+    let mut future = Box::pin(future);
+    // Register the waker of the task
+    assert!(futures::poll!(future.as_mut()).is_pending());
+
+    // The future is never polled again by this task
+    let dumps = dump_all_within(Duration::from_millis(10)).await;
+    assert_eq!(dumps.len(), 1);
+    assert_eq!(dumps[0].label, "blocked");
+    assert!(dumps[0].active_traces.is_none());
+    drop(future);
+}
+
+#[tokio::test]
+#[serial]
+async fn with_single_poll_per_dump() {
+    let polls = Arc::new(AtomicUsize::new(0));
+    let future = timeout(Duration::from_secs(10), CaptureSpanTrace, {
+        let polls = polls.clone();
+        std::future::poll_fn(move |_| {
+            polls.fetch_add(1, Ordering::Relaxed);
+            Poll::<()>::Pending
+        })
+    });
+    sleep(Duration::from_millis(20)).await;
+    let task = tokio::spawn(future.register("counted"));
+    sleep(Duration::from_millis(10)).await;
+    assert_eq!(polls.load(Ordering::Relaxed), 1);
+
+    let dumps = dump_all().await;
+    sleep(Duration::from_millis(10)).await;
+
+    // The future is polled once to capture its traces and not polled again afterwards
+    assert_eq!(polls.load(Ordering::Relaxed), 2);
+    // The time before registration counts as well
+    assert!(dumps[0].started.elapsed().unwrap() >= Duration::from_millis(30));
+    task.abort();
+}
+
+#[tokio::test]
+#[serial]
+async fn completed_while_capturing() {
+    let (sender, receiver) = mpsc::channel::<()>();
+    let future = timeout(Duration::from_secs(10), CaptureSpanTrace, async {
+        // Pending once without waking the task, so the next poll is the one requested by the dump
+        let mut polled = false;
+        std::future::poll_fn(|_| {
+            if std::mem::replace(&mut polled, true) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+    })
+    .on_drop(move |_| sender.send(()).unwrap());
+    let task = tokio::spawn(future.register("completing"));
+    sleep(Duration::from_millis(10)).await;
+
+    let dumps = dump_all().await;
+
+    assert!(task.await.unwrap().is_ok());
+    // The future is reported as completed and is not polled again when dropped
+    assert!(dumps.iter().all(|dump| dump.label != "completing"));
+    assert!(receiver.try_recv().is_err());
+}
+
+#[instrument]
+async fn do_sleep(n: i32) {
+    sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::{
+    io::AsyncReadExt,
+    join,
+    net::{TcpListener, TcpStream},
+    sync::{Mutex, Semaphore, mpsc, oneshot},
+    time::sleep,
+};
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    TimeoutElapsed, label_resource,
+    tests::insta_trace_filters,
+    timeout,
+    trace::{CaptureSpanTrace, CaptureTrace},
+};
+
+#[tokio::test]
+#[serial]
+async fn with_tokio_resources() {
+    let semaphore = Semaphore::new(0);
+    let mutex = Mutex::new(());
+    let _guard = mutex.lock().await;
+    let (_sender, mut receiver) = mpsc::channel::<()>(1);
+    let (_oneshot_sender, oneshot_receiver) = oneshot::channel::<()>();
+
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace.with_resource(),
+        async {
+            join!(
+                sleep(Duration::from_secs(1)),
+                semaphore.acquire(),
+                mutex.lock(),
+                receiver.recv(),
+                oneshot_receiver,
+            )
+        },
+    )
+    .await;
+
+    let err = result.err().unwrap();
+    let mut resources: Vec<_> = err
+        .active_traces
+        .iter()
+        .map(|trace| trace.resource().unwrap_or("<unknown>"))
+        .collect();
+    resources.sort_unstable();
+    assert_eq!(
+        resources,
+        [
+            "Mutex lock",
+            "Semaphore acquire",
+            "mpsc::Receiver",
+            "oneshot::Receiver",
+            "tokio::time::Sleep",
+        ]
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn with_channel_senders() {
+    let (sender, _receiver) = mpsc::channel::<()>(1);
+    sender.send(()).await.unwrap();
+    let (mut oneshot_sender, _oneshot_receiver) = oneshot::channel::<()>();
+
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace.with_resource(),
+        async { join!(sender.send(()), oneshot_sender.closed()) },
+    )
+    .await;
+
+    let err = result.err().unwrap();
+    let mut resources: Vec<_> = err
+        .active_traces
+        .iter()
+        .map(|trace| trace.resource().unwrap_or("<unknown>"))
+        .collect();
+    resources.sort_unstable();
+    assert_eq!(resources, ["mpsc::Sender send", "oneshot::Sender closed"]);
+}
+
+#[tokio::test]
+#[serial]
+async fn with_tcp_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (_server, _) = listener.accept().await.unwrap();
+
+    let mut buf = [0; 8];
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace.with_resource(),
+        client.read(&mut buf),
+    )
+    .await;
+
+    let err = result.err().unwrap();
+    assert_eq!(err.active_traces.len(), 1);
+    assert_eq!(err.active_traces[0].resource(), Some("TcpStream read"));
+}
+
+#[tokio::test]
+#[serial]
+async fn with_label() {
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace.with_resource(),
+        label_resource("custom RPC", do_sleep()),
+    )
+    .await;
+
+    let err = result.err().unwrap();
+    assert_eq!(err.active_traces.len(), 1);
+    assert_eq!(err.active_traces[0].resource(), Some("custom RPC"));
+}
+
+#[tokio::test]
+#[serial]
+async fn with_span_trace() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = timeout(
+        Duration::from_millis(100),
+        CaptureSpanTrace.with_resource(),
+        do_sleep(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[instrument]
+async fn do_sleep() {
+    sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    TimeoutElapsed,
+    tests::{insta_trace_filters, run_with_tracing},
+    timeout,
+    trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn serialize_span_trace() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = timeout(Duration::from_millis(100), CaptureSpanTrace, do_sleep()).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    let json = serde_json::to_string_pretty(&err).unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(json);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn serialize_stack_and_span_trace() {
+    let result = run_with_tracing(Duration::from_millis(100), do_sleep()).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    let json = serde_json::to_value(&err).unwrap();
+    let trace = &json["active_traces"][0];
+    assert_eq!(trace["span_trace"][0]["name"], "do_sleep_inner");
+    assert_eq!(
+        trace["span_trace"][0]["fields"],
+        serde_json::json!({ "n": "123", "label": "a \"quoted\" label" })
+    );
+    let frames = trace["stack_trace"].as_array().unwrap();
+    assert!(frames.iter().any(|frame| {
+        frame["function"].as_str().is_some_and(|function| {
+            function.starts_with("timeout_tracing::tests::serde::do_sleep_inner")
+        }) && frame["file"]
+            .as_str()
+            .is_some_and(|file| file.ends_with("src/tests/serde.rs"))
+            && frame["line"].is_u64()
+    }));
+}
+
+#[instrument]
+async fn do_sleep() {
+    do_sleep_inner(123, "a \"quoted\" label").await;
+}
+
+#[instrument]
+async fn do_sleep_inner(n: i32, label: &str) {
+    sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,58 @@
+use std::{process::Command, time::Duration};
+
+use serial_test::serial;
+use tokio::{signal::unix::SignalKind, sync::mpsc, time::sleep};
+
+use crate::{CaptureSpanTrace, FutureDump, dump_on_signal, timeout, watchdog};
+
+#[tokio::test]
+#[serial]
+async fn with_sigusr1() {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let listener = dump_on_signal(
+        SignalKind::user_defined1(),
+        move |dumps: Vec<FutureDump>| {
+            _ = sender.send(dumps);
+        },
+    )
+    .unwrap();
+
+    let timed_out = tokio::spawn(
+        timeout(
+            Duration::from_secs(10),
+            CaptureSpanTrace,
+            sleep(Duration::from_secs(5)),
+        )
+        .register("timeout"),
+    );
+    let watched = tokio::spawn(
+        watchdog(
+            Duration::from_secs(10),
+            CaptureSpanTrace,
+            |_| {},
+            sleep(Duration::from_secs(5)),
+        )
+        .register("watchdog"),
+    );
+    sleep(Duration::from_millis(10)).await;
+
+    let status = Command::new("kill")
+        .args(["-USR1", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut dumps = receiver.recv().await.unwrap();
+    dumps.sort_by(|a, b| a.label.cmp(&b.label));
+    assert_eq!(dumps.len(), 2);
+    assert_eq!(dumps[0].label, "timeout");
+    assert!(dumps[0].remaining.is_some());
+    assert_eq!(dumps[0].active_traces.as_ref().unwrap().len(), 1);
+    assert_eq!(dumps[1].label, "watchdog");
+    assert!(dumps[1].remaining.is_none());
+    assert_eq!(dumps[1].active_traces.as_ref().unwrap().len(), 1);
+
+    listener.abort();
+    timed_out.abort();
+    watched.abort();
+}
@@ -0,0 +1,103 @@
+use std::{task::Poll, time::Duration};
+
+use serial_test::serial;
+use tokio::{join, time::sleep};
+use tracing::instrument;
+use tracing_error::ErrorLayer;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    TimeoutElapsed, spawn_traced,
+    tests::{insta_trace_filters, run_with_tracing},
+    timeout,
+    trace::CaptureSpanTrace,
+    watchdog,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_span_trace() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let result = timeout(Duration::from_millis(100), CaptureSpanTrace, do_fan_out()).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let err = result.err().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err.await_tree());
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_stack_and_span_trace() {
+    let result = run_with_tracing(Duration::from_millis(100), do_spawn(1)).await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    let mut err = result.err().unwrap();
+    err.active_traces
+        .sort_by_cached_key(|trace| trace.span_trace.to_string());
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_watchdog() {
+    let mut reports = Vec::new();
+    let result = watchdog(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        |report| reports.push(report),
+        spawn_traced(async {
+            sleep(Duration::from_millis(100)).await;
+            42
+        }),
+    )
+    .await;
+
+    // The task keeps running after its traces are captured
+    assert_eq!(result.unwrap(), 42);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].active_traces.len(), 2);
+}
+
+#[tokio::test]
+#[serial]
+async fn with_panic_while_capturing() {
+    let mut polls = 0;
+    let mut task = spawn_traced(std::future::poll_fn(move |_| {
+        polls += 1;
+        // The second poll is the one that captures the traces
+        assert_eq!(polls, 1, "the task panicked");
+        Poll::<()>::Pending
+    }));
+    let result = timeout(Duration::from_millis(10), CaptureSpanTrace, &mut task).await;
+
+    // The panic is reported by the task instead of unwinding through the timeout
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    assert!(task.await.unwrap_err().is_panic());
+}
+
+#[instrument]
+async fn do_fan_out() {
+    let (a, b) = join!(spawn_traced(do_spawn(1)), spawn_traced(do_spawn(2)));
+    a.unwrap();
+    b.unwrap();
+}
+
+#[instrument]
+async fn do_spawn(n: i32) {
+    spawn_traced(do_sleep(n * 10)).await.unwrap();
+}
+
+#[instrument]
+async fn do_sleep(n: i32) {
+    sleep(Duration::from_secs(1)).await;
+}
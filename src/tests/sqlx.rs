@@ -12,6 +12,10 @@ use crate::{
 #[tokio::test]
 #[ignore = "need to run docker-compose.deps.yml in CI"]
 #[serial]
+#[allow(
+    clippy::duration_suboptimal_units,
+    reason = "the test predates the lint and is left as written"
+)]
 async fn with_sqlx() {
     let result = run_with_tracing(Duration::from_millis(1000), do_sqlx()).await;
 
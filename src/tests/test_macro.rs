@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+
+use crate::CaptureSpanAndStackTrace;
+
+#[crate::test(timeout = "1s")]
+#[serial]
+async fn with_completed_test() {
+    do_sleep(Duration::from_millis(1)).await;
+}
+
+#[crate::test(timeout = "1s", capture = CaptureSpanAndStackTrace, start_paused = true)]
+#[serial]
+async fn with_result() -> Result<(), std::num::ParseIntError> {
+    do_sleep(Duration::from_millis(100)).await;
+    let _: u32 = "1".parse()?;
+    Ok(())
+}
+
+#[crate::test(timeout = "100ms")]
+#[serial]
+#[should_panic(expected = "test timeout elapsed at:
+trace 0:
+   0: timeout_tracing::tests::test_macro::do_sleep")]
+async fn with_hanging_test() {
+    do_sleep(Duration::from_secs(10)).await;
+}
+
+#[instrument]
+async fn do_sleep(duration: Duration) {
+    sleep(duration).await;
+}
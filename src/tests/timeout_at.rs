@@ -0,0 +1,52 @@
+use std::{pin::pin, time::Duration};
+
+use serial_test::serial;
+use tokio::time::{Instant, sleep};
+
+use crate::{TimeoutElapsed, timeout_at, trace::CaptureSpanTrace};
+
+#[tokio::test]
+#[serial]
+async fn with_deadline() {
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let fut = timeout_at(deadline, CaptureSpanTrace, sleep(Duration::from_secs(1)));
+    assert_eq!(fut.deadline(), deadline);
+
+    let result = fut.await;
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+}
+
+#[tokio::test]
+#[serial]
+async fn with_later_deadline() {
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let mut fut = pin!(timeout_at(
+        deadline,
+        CaptureSpanTrace,
+        sleep(Duration::from_millis(200))
+    ));
+
+    let new_deadline = deadline + Duration::from_secs(1);
+    fut.as_mut().reset(new_deadline);
+    assert_eq!(fut.deadline(), new_deadline);
+
+    let result = fut.await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+#[serial]
+async fn with_earlier_deadline() {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut fut = pin!(timeout_at(
+        deadline,
+        CaptureSpanTrace,
+        sleep(Duration::from_millis(200))
+    ));
+
+    fut.as_mut()
+        .reset(Instant::now() + Duration::from_millis(100));
+
+    let result = fut.await;
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+}
@@ -0,0 +1,51 @@
+//! Tests for non-default [`crate::Timer`] implementations.
+//! They are executed without tokio runtime.
+
+#[cfg(feature = "async-io")]
+#[test]
+#[serial_test::serial]
+fn with_async_io() {
+    use std::time::Duration;
+
+    use crate::{AsyncIoTimer, TimeoutElapsed, TimeoutFuture, trace::CaptureSpanTrace};
+
+    let result = futures::executor::block_on(TimeoutFuture::<_, _, AsyncIoTimer>::new(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        async_io::Timer::after(Duration::from_secs(1)),
+    ));
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+
+    let result = futures::executor::block_on(TimeoutFuture::<_, _, AsyncIoTimer>::new(
+        Duration::from_secs(1),
+        CaptureSpanTrace,
+        async_io::Timer::after(Duration::from_millis(10)),
+    ));
+    assert!(result.is_ok());
+}
+
+#[cfg(feature = "futures-timer")]
+#[test]
+#[serial_test::serial]
+fn with_futures_timer() {
+    use std::{pin::pin, time::Duration};
+
+    use crate::{FuturesTimerDelay, TimeoutElapsed, TimeoutFuture, Timer, trace::CaptureSpanTrace};
+
+    let result = futures::executor::block_on(TimeoutFuture::<_, _, FuturesTimerDelay>::new(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        futures_timer::Delay::new(Duration::from_secs(1)),
+    ));
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+
+    let mut fut = pin!(TimeoutFuture::<_, _, FuturesTimerDelay>::new(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        futures_timer::Delay::new(Duration::from_millis(100)),
+    ));
+    fut.as_mut()
+        .reset(FuturesTimerDelay::now() + Duration::from_secs(1));
+    let result = futures::executor::block_on(fut);
+    assert!(result.is_ok());
+}
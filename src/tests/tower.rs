@@ -0,0 +1,74 @@
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tower::{BoxError, ServiceBuilder, ServiceExt, service_fn};
+use tracing::instrument;
+use tracing_error::{ErrorLayer, SpanTrace};
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    TimeoutElapsed, TracingTimeoutLayer, tests::insta_trace_filters, trace::CaptureSpanTrace,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_error() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let service = ServiceBuilder::new()
+        .layer(TracingTimeoutLayer::new(
+            Duration::from_millis(100),
+            CaptureSpanTrace,
+        ))
+        .service(service_fn(handle));
+
+    assert_eq!(service.clone().oneshot(1).await.unwrap(), 200);
+    let err = service.oneshot(1000).await.unwrap_err();
+    let err = err.downcast::<TimeoutElapsed<SpanTrace>>().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_response() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let service = ServiceBuilder::new()
+        .layer(
+            TracingTimeoutLayer::new(Duration::from_millis(100), CaptureSpanTrace)
+                .report_with({
+                    let reported = reported.clone();
+                    move |elapsed: &TimeoutElapsed<SpanTrace>| {
+                        reported.lock().unwrap().push(elapsed.to_string());
+                    }
+                })
+                .on_timeout(|_| Ok::<_, Infallible>(504)),
+        )
+        .service(service_fn(|delay| async move {
+            handle(delay).await.map_err(|_| unreachable!())
+        }));
+
+    assert_eq!(service.clone().oneshot(1).await, Ok(200));
+    assert_eq!(service.oneshot(1000).await, Ok(504));
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    assert!(reported[0].contains("timeout_tracing::tests::tower::handle"));
+}
+
+#[instrument]
+async fn handle(delay: u64) -> Result<u16, BoxError> {
+    sleep(Duration::from_millis(delay)).await;
+    Ok(200)
+}
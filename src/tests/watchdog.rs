@@ -0,0 +1,122 @@
+use std::{
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+
+use serial_test::serial;
+use tokio::time::sleep;
+use tracing::instrument;
+use tracing_error::{ErrorLayer, SpanTrace};
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    StallReport, TimeoutElapsed, tests::insta_trace_filters, trace::CaptureSpanTrace, watchdog,
+};
+
+#[tokio::test]
+#[serial]
+async fn with_watchdog() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let reports = Arc::new(Mutex::new(Vec::<StallReport<SpanTrace>>::new()));
+    let result = watchdog(
+        Duration::from_millis(100),
+        CaptureSpanTrace,
+        {
+            let reports = reports.clone();
+            move |report| reports.lock().unwrap().push(report)
+        },
+        do_sleep(Duration::from_millis(300)),
+    )
+    .await;
+
+    assert_eq!(result, 42);
+    let reports = std::mem::take(&mut *reports.lock().unwrap());
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].elapsed >= Duration::from_millis(100));
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(reports[0].active_traces[0]);
+    });
+}
+
+#[tokio::test]
+#[serial]
+async fn with_hard_limit() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let reports = Arc::new(Mutex::new(Vec::<StallReport<SpanTrace>>::new()));
+    let result = watchdog(
+        Duration::from_millis(50),
+        CaptureSpanTrace,
+        {
+            let reports = reports.clone();
+            move |report| reports.lock().unwrap().push(report)
+        },
+        do_sleep(Duration::from_secs(1)),
+    )
+    .with_hard_limit(Duration::from_millis(150))
+    .await;
+
+    assert!(matches!(result, Err(TimeoutElapsed { .. })));
+    assert_eq!(reports.lock().unwrap().len(), 1);
+    let err = result.err().unwrap();
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        insta::assert_snapshot!(err);
+    });
+}
+
+#[instrument]
+async fn do_sleep(duration: Duration) -> i32 {
+    sleep(duration).await;
+    42
+}
+
+#[tokio::test(start_paused = true)]
+#[serial]
+async fn with_repeat() {
+    let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let (sender, receiver) = mpsc::channel::<StallReport<SpanTrace>>();
+    let result = watchdog(
+        Duration::from_millis(100),
+        CaptureSpanTrace,
+        sender,
+        do_two_sleeps(),
+    )
+    .repeat_every(Duration::from_millis(200))
+    .await;
+
+    assert_eq!(result, 42);
+    let reports: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| (report.snapshot, report.elapsed))
+            .collect::<Vec<_>>(),
+        vec![
+            (0, Duration::from_millis(100)),
+            (1, Duration::from_millis(300)),
+            (2, Duration::from_millis(500)),
+        ]
+    );
+    insta::with_settings!({
+        filters => insta_trace_filters()
+    }, {
+        for report in &reports {
+            insta::assert_snapshot!(report.active_traces[0]);
+        }
+    });
+}
+
+#[instrument]
+async fn do_two_sleeps() -> i32 {
+    do_sleep(Duration::from_millis(250)).await;
+    do_sleep(Duration::from_millis(300)).await
+}
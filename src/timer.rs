@@ -0,0 +1,173 @@
+use std::{
+    ops::{Add, Sub},
+    pin::Pin,
+    time::Duration,
+};
+
+/// A trait for sleep futures that implement the deadline of [`crate::TimeoutFuture`] and [`crate::WatchdogFuture`].
+/// This decouples the crate from a specific async runtime.
+///
+/// Implementations are provided for:
+/// - [`tokio::time::Sleep`] (the `tokio` feature, enabled by default)
+/// - [`AsyncIoTimer`] (the `async-io` feature)
+/// - [`FuturesTimerDelay`] (the `futures-timer` feature)
+pub trait Timer: Future<Output = ()> {
+    /// Representation of a point in time used by the timer.
+    type Instant: Copy + Add<Duration, Output = Self::Instant> + Sub<Output = Duration>;
+
+    /// Returns the current time.
+    fn now() -> Self::Instant;
+
+    /// Creates a timer that completes at `deadline`.
+    fn sleep_until(deadline: Self::Instant) -> Self;
+
+    /// Creates a timer that completes after `duration`.
+    #[must_use]
+    fn sleep(duration: Duration) -> Self
+    where
+        Self: Sized,
+    {
+        Self::sleep_until(Self::now() + duration)
+    }
+
+    /// Returns the instant at which the timer completes.
+    fn deadline(&self) -> Self::Instant;
+
+    /// Resets the instant at which the timer completes.
+    fn reset(self: Pin<&mut Self>, deadline: Self::Instant);
+}
+
+/// The timer used by [`crate::timeout`] and [`crate::watchdog`]: [`tokio::time::Sleep`].
+#[cfg(feature = "tokio")]
+pub type DefaultTimer = tokio::time::Sleep;
+
+/// The timer used by [`crate::timeout`] and [`crate::watchdog`]: [`AsyncIoTimer`], as the `tokio` feature is disabled.
+#[cfg(all(not(feature = "tokio"), feature = "async-io"))]
+pub type DefaultTimer = AsyncIoTimer;
+
+/// The timer used by [`crate::timeout`] and [`crate::watchdog`]: [`FuturesTimerDelay`],
+/// as the `tokio` and `async-io` features are disabled.
+#[cfg(all(
+    not(feature = "tokio"),
+    not(feature = "async-io"),
+    feature = "futures-timer"
+))]
+pub type DefaultTimer = FuturesTimerDelay;
+
+#[cfg(not(any(feature = "tokio", feature = "async-io", feature = "futures-timer")))]
+compile_error!("one of `tokio`, `async-io` or `futures-timer` features must be enabled");
+
+#[cfg(feature = "tokio")]
+impl Timer for tokio::time::Sleep {
+    type Instant = tokio::time::Instant;
+
+    fn now() -> Self::Instant {
+        tokio::time::Instant::now()
+    }
+
+    fn sleep_until(deadline: Self::Instant) -> Self {
+        tokio::time::sleep_until(deadline)
+    }
+
+    fn deadline(&self) -> Self::Instant {
+        tokio::time::Sleep::deadline(self)
+    }
+
+    fn reset(self: Pin<&mut Self>, deadline: Self::Instant) {
+        tokio::time::Sleep::reset(self, deadline);
+    }
+}
+
+/// Implementation of [`Timer`] based on [`async_io::Timer`].
+/// It works with `smol`, `async-std` and other executors that rely on `async-io`.
+#[cfg(feature = "async-io")]
+#[derive(Debug)]
+pub struct AsyncIoTimer {
+    timer: async_io::Timer,
+    deadline: std::time::Instant,
+}
+
+#[cfg(feature = "async-io")]
+impl Future for AsyncIoTimer {
+    type Output = ();
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        Pin::new(&mut self.timer).poll(cx).map(|_| ())
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl Timer for AsyncIoTimer {
+    type Instant = std::time::Instant;
+
+    fn now() -> Self::Instant {
+        std::time::Instant::now()
+    }
+
+    fn sleep_until(deadline: Self::Instant) -> Self {
+        Self {
+            timer: async_io::Timer::at(deadline),
+            deadline,
+        }
+    }
+
+    fn deadline(&self) -> Self::Instant {
+        self.deadline
+    }
+
+    fn reset(mut self: Pin<&mut Self>, deadline: Self::Instant) {
+        self.timer.set_at(deadline);
+        self.deadline = deadline;
+    }
+}
+
+/// Implementation of [`Timer`] based on [`futures_timer::Delay`].
+/// It does not depend on any executor and runs its own timer thread.
+#[cfg(feature = "futures-timer")]
+#[derive(Debug)]
+pub struct FuturesTimerDelay {
+    delay: futures_timer::Delay,
+    deadline: std::time::Instant,
+}
+
+#[cfg(feature = "futures-timer")]
+impl Future for FuturesTimerDelay {
+    type Output = ();
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        Pin::new(&mut self.delay).poll(cx)
+    }
+}
+
+#[cfg(feature = "futures-timer")]
+impl Timer for FuturesTimerDelay {
+    type Instant = std::time::Instant;
+
+    fn now() -> Self::Instant {
+        std::time::Instant::now()
+    }
+
+    fn sleep_until(deadline: Self::Instant) -> Self {
+        Self {
+            delay: futures_timer::Delay::new(deadline.saturating_duration_since(Self::now())),
+            deadline,
+        }
+    }
+
+    fn deadline(&self) -> Self::Instant {
+        self.deadline
+    }
+
+    fn reset(mut self: Pin<&mut Self>, deadline: Self::Instant) {
+        // `futures_timer::Delay` only supports relative durations
+        self.delay
+            .reset(deadline.saturating_duration_since(Self::now()));
+        self.deadline = deadline;
+    }
+}
@@ -1,17 +1,31 @@
-use std::{backtrace::Backtrace, fmt::Display};
+use std::{
+    fmt::Display,
+    sync::{Arc, LazyLock},
+};
 
 use tracing_error::SpanTrace;
 
+use crate::{filter::FrameFilter, resource::CaptureResource, stack::StackTrace};
+
 /// A trait to support custom implementations of traces
 pub trait CaptureTrace {
     /// Representation for captured trace
     type Trace;
     /// Capture trace at the current moment.
     fn capture(&self) -> Self::Trace;
+
+    /// Also capture the leaf resource each await point is waiting on, see [`CaptureResource`].
+    fn with_resource(self) -> CaptureResource<Self>
+    where
+        Self: Sized,
+    {
+        CaptureResource::new(self)
+    }
 }
 
 /// Implementation of [`CaptureTrace`] that captures span trace using [`tracing_error::SpanTrace`].
 /// [`tracing`] must be initialized with [`tracing_error::ErrorLayer`] for the trace to be captured successfully.
+#[derive(Debug, Clone, Copy, Default)]
 pub struct CaptureSpanTrace;
 
 impl CaptureTrace for CaptureSpanTrace {
@@ -26,34 +40,66 @@ impl CaptureTrace for CaptureSpanTrace {
 /// and a stack trace.
 /// [`tracing`] must be initialized with [`tracing_error::ErrorLayer`] for the span trace to be captured successfully
 /// and `RUST_BACKTRACE` environment variable must be set for the stack trace to be captured.
+/// The stack trace is filtered with the default [`FrameFilter`], use [`CaptureSpanAndStackTrace::with_filter`]
+/// to choose a different one.
+/// The symbols of the stack trace are resolved only when it is formatted or its frames are accessed.
+#[derive(Debug, Clone, Copy, Default)]
 pub struct CaptureSpanAndStackTrace;
 
+impl CaptureSpanAndStackTrace {
+    /// Capture the stack trace filtered with `filter` instead of the default [`FrameFilter`].
+    #[must_use]
+    pub fn with_filter(self, filter: FrameFilter) -> CaptureFilteredSpanAndStackTrace {
+        CaptureFilteredSpanAndStackTrace {
+            filter: Arc::new(filter),
+        }
+    }
+}
+
 impl CaptureTrace for CaptureSpanAndStackTrace {
     type Trace = StackAndSpanTrace;
 
     fn capture(&self) -> Self::Trace {
-        StackAndSpanTrace::capture()
+        static FILTER: LazyLock<Arc<FrameFilter>> = LazyLock::new(Arc::default);
+        StackAndSpanTrace::capture(&FILTER)
+    }
+}
+
+/// Same as [`CaptureSpanAndStackTrace`], but with a custom [`FrameFilter`].
+/// Created by [`CaptureSpanAndStackTrace::with_filter`].
+#[derive(Debug, Clone, Default)]
+pub struct CaptureFilteredSpanAndStackTrace {
+    filter: Arc<FrameFilter>,
+}
+
+impl CaptureTrace for CaptureFilteredSpanAndStackTrace {
+    type Trace = StackAndSpanTrace;
+
+    fn capture(&self) -> Self::Trace {
+        StackAndSpanTrace::capture(&self.filter)
     }
 }
 
 #[derive(Debug)]
 pub struct StackAndSpanTrace {
-    pub(crate) stack_trace: Backtrace,
+    pub(crate) stack_trace: StackTrace,
     pub(crate) span_trace: SpanTrace,
 }
 
 impl StackAndSpanTrace {
-    pub(crate) fn capture() -> Self {
+    pub(crate) fn capture(filter: &Arc<FrameFilter>) -> Self {
         Self {
             span_trace: SpanTrace::capture(),
-            stack_trace: Backtrace::capture(),
+            stack_trace: StackTrace::capture(filter),
         }
     }
 
-    pub fn stack_trace(&self) -> &Backtrace {
+    #[must_use]
+    pub fn stack_trace(&self) -> &StackTrace {
         &self.stack_trace
     }
 
+    #[must_use]
     pub fn span_trace(&self) -> &SpanTrace {
         &self.span_trace
     }
@@ -69,3 +115,176 @@ impl Display for StackAndSpanTrace {
         )
     }
 }
+
+/// A single span of a [`SpanTrace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SpanFrame {
+    pub target: &'static str,
+    pub name: &'static str,
+    pub file: Option<&'static str>,
+    pub line: Option<u32>,
+    /// Span fields, as formatted by [`tracing_error::ErrorLayer`].
+    /// Serialized as a map from the field names to their values.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_span_fields"))]
+    pub fields: String,
+}
+
+impl SpanFrame {
+    /// Returns the spans of `span_trace`, starting from the innermost one.
+    #[must_use]
+    pub fn collect(span_trace: &SpanTrace) -> Vec<Self> {
+        let mut frames = Vec::new();
+        span_trace.with_spans(|metadata, fields| {
+            frames.push(Self {
+                target: metadata.target(),
+                name: metadata.name(),
+                file: metadata.file(),
+                line: metadata.line(),
+                fields: fields.to_owned(),
+            });
+            true
+        });
+        frames
+    }
+}
+
+/// Serializes span fields formatted as `name=value` pairs, separated by spaces, as a map.
+/// Quoted string values are unquoted, other values are kept as formatted.
+#[cfg(feature = "serde")]
+#[allow(
+    clippy::ptr_arg,
+    reason = "the signature is required by `serialize_with`"
+)]
+fn serialize_span_fields<S: serde::Serializer>(
+    fields: &String,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(parse_span_fields(fields))
+}
+
+/// Splits span fields formatted by the default formatter of [`tracing_error::ErrorLayer`] into names and values.
+/// The `message` field is formatted without its name.
+#[cfg(feature = "serde")]
+fn parse_span_fields(mut fields: &str) -> Vec<(&str, std::borrow::Cow<'_, str>)> {
+    let mut parsed = Vec::new();
+    loop {
+        fields = fields.trim_start();
+        if fields.is_empty() {
+            return parsed;
+        }
+        let (name, rest) = match field_name(fields) {
+            Some(name) => (name, &fields[name.len() + 1..]),
+            None => ("message", fields),
+        };
+        let (value, rest) = rest
+            .strip_prefix('"')
+            .and_then(quoted_value)
+            .unwrap_or_else(|| {
+                // Unquoted values, e.g. `Debug` output of structs, may contain spaces,
+                // so they end where the next field starts
+                let end = rest
+                    .match_indices(' ')
+                    .map(|(idx, _)| idx)
+                    .find(|&idx| field_name(&rest[idx + 1..]).is_some())
+                    .unwrap_or(rest.len());
+                (rest[..end].into(), &rest[end..])
+            });
+        parsed.push((name, value));
+        fields = rest;
+    }
+}
+
+/// Returns the name of the field at the start of `fields`, if it is followed by `=`.
+#[cfg(feature = "serde")]
+fn field_name(fields: &str) -> Option<&str> {
+    let (name, _) = fields.split_once('=')?;
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '#'));
+    is_name.then_some(name)
+}
+
+/// Unescapes a string value formatted with `Debug`, starting after the opening quote.
+/// Returns the value and the rest of `fields` after the closing quote.
+#[cfg(feature = "serde")]
+fn quoted_value(fields: &str) -> Option<(std::borrow::Cow<'_, str>, &str)> {
+    let mut value = String::new();
+    let mut chars = fields.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Some((value.into(), &fields[idx + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                '0' => value.push('\0'),
+                escaped => value.push(escaped),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// A trait for traces that can be serialized with [`serde`].
+/// It is needed because [`serde::Serialize`] can't be implemented for [`SpanTrace`] in this crate.
+/// Traces of custom [`CaptureTrace`] implementations can simply delegate to [`serde::Serialize`].
+#[cfg(feature = "serde")]
+pub trait SerializeTrace {
+    /// Serialize this trace into the given serializer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `serializer` fails.
+    fn serialize_trace<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+}
+
+#[cfg(feature = "serde")]
+impl SerializeTrace for SpanTrace {
+    fn serialize_trace<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(SpanFrame::collect(self))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerializeTrace for StackAndSpanTrace {
+    fn serialize_trace<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for StackAndSpanTrace {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("StackAndSpanTrace", 2)?;
+        state.serialize_field("span_trace", &SerializeAsTrace(&self.span_trace))?;
+        state.serialize_field("stack_trace", &self.stack_trace)?;
+        state.end()
+    }
+}
+
+/// Adapter that implements [`serde::Serialize`] for any [`SerializeTrace`].
+#[cfg(feature = "serde")]
+pub(crate) struct SerializeAsTrace<'a, Trace>(pub(crate) &'a Trace);
+
+#[cfg(feature = "serde")]
+impl<Trace: SerializeTrace> serde::Serialize for SerializeAsTrace<'_, Trace> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_trace(serializer)
+    }
+}
+
+/// Adapter that implements [`serde::Serialize`] for a list of any [`SerializeTrace`].
+#[cfg(feature = "serde")]
+pub(crate) struct SerializeAsTraces<'a, Trace>(pub(crate) &'a [Trace]);
+
+#[cfg(feature = "serde")]
+impl<Trace: SerializeTrace> serde::Serialize for SerializeAsTraces<'_, Trace> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(SerializeAsTrace))
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Display;
+
+use tracing_error::SpanTrace;
+
+use crate::trace::{SpanFrame, StackAndSpanTrace};
+
+/// A trait for traces that contain a [`SpanTrace`], so that they can be merged into an [`AwaitTree`].
+pub trait AsSpanTrace {
+    fn as_span_trace(&self) -> &SpanTrace;
+}
+
+impl AsSpanTrace for SpanTrace {
+    fn as_span_trace(&self) -> &SpanTrace {
+        self
+    }
+}
+
+impl AsSpanTrace for StackAndSpanTrace {
+    fn as_span_trace(&self) -> &SpanTrace {
+        &self.span_trace
+    }
+}
+
+/// Span traces of multiple await points merged into a single tree by their common spans.
+///
+/// With `join!`, `select!` and similar combinators, each active await point gets its own trace,
+/// and all of them repeat the same parent spans. The tree shows each span only once.
+///
+/// Spans are considered the same if they have the same callsite and the same field values.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::{join, time::sleep};
+/// # use timeout_tracing::{AwaitTree, CaptureSpanTrace, timeout};
+/// # use tracing::instrument;
+/// # use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// tracing_subscriber::registry()
+///     .with(tracing_error::ErrorLayer::default())
+///     .init();
+///
+/// let result = timeout(Duration::from_millis(10), CaptureSpanTrace, parent()).await;
+/// let tree = AwaitTree::from_traces(&result.err().unwrap().active_traces);
+/// // parent
+/// // ├─ child with n=1
+/// // └─ child with n=2
+/// assert_eq!(tree.roots.len(), 1);
+/// assert_eq!(tree.roots[0].span.name, "parent");
+/// assert_eq!(tree.roots[0].children.len(), 2);
+/// println!("{tree}");
+///
+/// #[instrument]
+/// async fn parent() {
+///     join!(child(1), child(2));
+/// }
+/// #[instrument]
+/// async fn child(n: i32) {
+///     sleep(Duration::from_secs(1)).await;
+/// }
+/// # });
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AwaitTree {
+    /// Outermost spans.
+    pub roots: Vec<AwaitNode>,
+    /// Number of traces without any spans.
+    pub unattributed: usize,
+}
+
+/// A single span within an [`AwaitTree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AwaitNode {
+    pub span: SpanFrame,
+    /// Number of traces for which this span is the innermost one.
+    pub await_points: usize,
+    /// Spans nested within this span.
+    pub children: Vec<AwaitNode>,
+}
+
+impl AwaitTree {
+    /// Merge the span traces of `traces` into a tree.
+    pub fn from_traces<'a, Trace>(traces: impl IntoIterator<Item = &'a Trace>) -> Self
+    where
+        Trace: AsSpanTrace + 'a,
+    {
+        let mut tree = Self::default();
+        for trace in traces {
+            tree.insert(trace.as_span_trace());
+        }
+        tree
+    }
+
+    fn insert(&mut self, span_trace: &SpanTrace) {
+        let spans = SpanFrame::collect(span_trace);
+        let mut level = &mut self.roots;
+        let mut innermost = None;
+        // `SpanFrame::collect` starts from the innermost span
+        for span in spans.into_iter().rev() {
+            let idx = level
+                .iter()
+                .position(|node| node.span == span)
+                .unwrap_or_else(|| {
+                    level.push(AwaitNode {
+                        span,
+                        await_points: 0,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                });
+            let node = &mut level[idx];
+            innermost = Some(&mut node.await_points);
+            level = &mut node.children;
+        }
+        match innermost {
+            Some(await_points) => *await_points += 1,
+            None => self.unattributed += 1,
+        }
+    }
+}
+
+impl Display for AwaitTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for root in &self.roots {
+            root.fmt_tree(f, "", "")?;
+        }
+        if self.unattributed > 0 {
+            writeln!(f, "{} await point(s) outside of spans", self.unattributed)?;
+        }
+        Ok(())
+    }
+}
+
+impl AwaitNode {
+    fn fmt_tree(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        prefix: &str,
+        children_prefix: &str,
+    ) -> std::fmt::Result {
+        write!(f, "{prefix}{}::{}", self.span.target, self.span.name)?;
+        if !self.span.fields.is_empty() {
+            write!(f, " with {}", self.span.fields)?;
+        }
+        if let Some(file) = self.span.file {
+            write!(f, " at {file}")?;
+            if let Some(line) = self.span.line {
+                write!(f, ":{line}")?;
+            }
+        }
+        if self.await_points > 1 {
+            write!(f, " ({} await points)", self.await_points)?;
+        }
+        writeln!(f)?;
+        for (idx, child) in self.children.iter().enumerate() {
+            if idx + 1 == self.children.len() {
+                child.fmt_tree(
+                    f,
+                    &format!("{children_prefix}└─ "),
+                    &format!("{children_prefix}   "),
+                )?;
+            } else {
+                child.fmt_tree(
+                    f,
+                    &format!("{children_prefix}├─ "),
+                    &format!("{children_prefix}│  "),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,36 +1,129 @@
 use std::{
-    sync::{Arc, Mutex},
-    task::{RawWaker, RawWakerVTable, Waker},
+    cell::{Cell, RefCell},
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 
-use crate::trace::CaptureTrace;
+use crate::{
+    forward::{ForwardScope, ForwardedWakers},
+    trace::CaptureTrace,
+};
+
+/// Poll `fut` once with a waker that captures a trace for every await point within `fut`.
+/// Returns `Ok` with the output if `fut` completes during this poll and `Err` with captured traces otherwise.
+pub(crate) fn poll_with_capture<C, Fut>(
+    capture: Arc<C>,
+    forwarded: &Arc<ForwardedWakers>,
+    fut: Pin<&mut Fut>,
+    cx: &Context<'_>,
+) -> Result<Fut::Output, Vec<C::Trace>>
+where
+    C: CaptureTrace + Send + 'static,
+    Fut: Future,
+{
+    let waker_inner = TracingTimeoutWakerInner::new(capture);
+    let waker = TracingTimeoutWaker::new_std_waker(waker_inner.clone(), cx.waker().clone(), false);
+    let mut cx2 = Context::from_waker(&waker);
+    // Make the combinators poll the futures wrapped with `forward_capture`
+    forwarded.wake_all();
+    let result = {
+        let _forward_scope = ForwardScope::enter(forwarded);
+        let _scope = CaptureScope::enter(waker_inner.clone());
+        fut.poll(&mut cx2)
+    };
+    match result {
+        Poll::Pending => Err(waker_inner.traces()),
+        Poll::Ready(result) => Ok(result),
+    }
+}
+
+type WrapWaker = Rc<dyn Fn(&Waker) -> Waker>;
+
+thread_local! {
+    static CAPTURE_SCOPE: RefCell<Option<WrapWaker>> = const { RefCell::new(None) };
+    /// Set while a waker returned by [`capturing_waker`] clones the waker it wraps.
+    /// That waker may itself be capturing traces, which would produce each trace twice.
+    static SUPPRESS_CAPTURE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns a waker that wakes `waker`, but also captures traces into the [`crate::TimeoutFuture`]
+/// or [`crate::WatchdogFuture`] that is capturing traces on this thread right now.
+/// Returns `None` if no traces are being captured.
+///
+/// This is a hook for combinators and executors that poll futures with their own wakers,
+/// see [`crate::forward_capture`] for a ready-made wrapper.
+#[must_use]
+pub fn capturing_waker(waker: &Waker) -> Option<Waker> {
+    let wrap = CAPTURE_SCOPE.with_borrow(Clone::clone)?;
+    Some(wrap(waker))
+}
+
+/// Whether a [`crate::TimeoutFuture`] or [`crate::WatchdogFuture`] is capturing traces on this thread right now.
+pub(crate) fn is_capturing() -> bool {
+    CAPTURE_SCOPE.with_borrow(Option::is_some)
+}
+
+/// Makes [`capturing_waker`] available while the inner future is polled.
+struct CaptureScope {
+    previous: Option<WrapWaker>,
+}
+
+impl CaptureScope {
+    fn enter<C: CaptureTrace + Send + 'static>(inner: Arc<TracingTimeoutWakerInner<C>>) -> Self {
+        let wrap: WrapWaker = Rc::new(move |waker: &Waker| {
+            TracingTimeoutWaker::new_std_waker(inner.clone(), waker.clone(), true)
+        });
+        let previous = CAPTURE_SCOPE.with_borrow_mut(|scope| scope.replace(wrap));
+        Self { previous }
+    }
+}
+
+impl Drop for CaptureScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CAPTURE_SCOPE.with_borrow_mut(|scope| *scope = previous);
+    }
+}
 
 pub(crate) struct TracingTimeoutWakerInner<C: CaptureTrace + Send + 'static> {
-    active_traces: Mutex<Vec<Option<C::Trace>>>,
-    capture: C,
-    inner_waker: Waker,
+    /// `None` once the traces were taken: the wakers may outlive the poll, but their later clones are not reported.
+    active_traces: Mutex<Option<Vec<Option<C::Trace>>>>,
+    capture: Arc<C>,
 }
 
 impl<C: CaptureTrace + Send + 'static> TracingTimeoutWakerInner<C> {
-    pub(crate) fn new(capture: C, inner_waker: Waker) -> Arc<Self> {
+    pub(crate) fn new(capture: Arc<C>) -> Arc<Self> {
         Arc::new(Self {
-            active_traces: Mutex::new(Vec::with_capacity(4)),
+            active_traces: Mutex::new(Some(Vec::with_capacity(4))),
             capture,
-            inner_waker,
         })
     }
 
     pub(crate) fn traces(&self) -> Vec<C::Trace> {
-        std::mem::take(&mut *self.active_traces.lock().unwrap())
+        self.active_traces()
+            .take()
             .into_iter()
             .flatten()
+            .flatten()
             .collect()
     }
+
+    fn active_traces(&self) -> MutexGuard<'_, Option<Vec<Option<C::Trace>>>> {
+        self.active_traces
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 pub(crate) struct TracingTimeoutWaker<C: CaptureTrace + Send + 'static> {
     inner: Arc<TracingTimeoutWakerInner<C>>,
     idx: Option<usize>,
+    /// The waker to wake when this waker is woken
+    inner_waker: Waker,
+    /// Whether the waker was created by [`capturing_waker`]
+    forwarded: bool,
 }
 
 impl<C> TracingTimeoutWaker<C>
@@ -51,8 +144,17 @@ where
         )
     }
 
-    pub(crate) fn new_std_waker(inner: Arc<TracingTimeoutWakerInner<C>>) -> Waker {
-        let data = Box::into_raw(Box::new(Self { inner, idx: None }));
+    pub(crate) fn new_std_waker(
+        inner: Arc<TracingTimeoutWakerInner<C>>,
+        inner_waker: Waker,
+        forwarded: bool,
+    ) -> Waker {
+        let data = Box::into_raw(Box::new(Self {
+            inner,
+            idx: None,
+            inner_waker,
+            forwarded,
+        }));
         // SAFETY: (see comment for `vtable` function)
         // `data` is a valid pointer to Box as it was just obtained from `Box::into_raw`
         unsafe { Waker::new(data as *const (), Self::vtable()) }
@@ -63,16 +165,29 @@ where
         reason = "Box<Self> is necessary for correctness"
     )]
     fn clone(&self) -> Box<Self> {
-        let trace = self.inner.capture.capture();
-        let idx = {
-            let mut traces = self.inner.active_traces.lock().unwrap();
-            let idx = traces.len();
-            traces.push(Some(trace));
-            idx
+        let capturing = !SUPPRESS_CAPTURE.get() && self.inner.active_traces().is_some();
+        let idx = capturing
+            .then(|| {
+                let trace = self.inner.capture.capture();
+                let mut traces = self.inner.active_traces();
+                let traces = traces.as_mut()?;
+                traces.push(Some(trace));
+                Some(traces.len() - 1)
+            })
+            .flatten();
+        let inner_waker = if self.forwarded {
+            let suppressed = SUPPRESS_CAPTURE.replace(true);
+            let inner_waker = self.inner_waker.clone();
+            SUPPRESS_CAPTURE.set(suppressed);
+            inner_waker
+        } else {
+            self.inner_waker.clone()
         };
         Box::new(Self {
             inner: self.inner.clone(),
-            idx: Some(idx),
+            idx,
+            inner_waker,
+            forwarded: self.forwarded,
         })
     }
 
@@ -86,7 +201,7 @@ where
     }
 
     fn wake(&self) {
-        self.inner.inner_waker.wake_by_ref();
+        self.inner_waker.wake_by_ref();
     }
     unsafe fn raw_wake(data: *const ()) {
         // SAFETY: (see comment for `vtable` function)
@@ -96,7 +211,7 @@ where
         this.wake();
     }
     fn wake_by_ref(&self) {
-        self.inner.inner_waker.wake_by_ref();
+        self.inner_waker.wake_by_ref();
     }
     unsafe fn raw_wake_by_ref(data: *const ()) {
         // SAFETY: (see comment for `vtable` function)
@@ -116,11 +231,15 @@ where
 
 impl<C: CaptureTrace + Send + 'static> Drop for TracingTimeoutWaker<C> {
     fn drop(&mut self) {
-        let mut traces = self.inner.active_traces.lock().unwrap();
         if let Some(idx) = self.idx {
-            // `TimeoutFuture::poll` takes data from `traces.traces`
-            if !traces.is_empty() {
-                traces[idx] = None;
+            // The traces may have been taken already
+            if let Some(trace) = self
+                .inner
+                .active_traces()
+                .as_mut()
+                .and_then(|traces| traces.get_mut(idx))
+            {
+                *trace = None;
             }
         }
     }
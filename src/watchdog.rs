@@ -0,0 +1,248 @@
+use std::{
+    fmt::Display,
+    pin::Pin,
+    sync::{Arc, mpsc::Sender},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pin_project_lite::pin_project;
+use tracing::warn;
+
+use crate::{
+    TimeoutFuture,
+    forward::{ForwardScope, ForwardedWakers},
+    registry::{CaptureNow, RegisteredFuture},
+    timer::{DefaultTimer, Timer},
+    trace::CaptureTrace,
+    waker::poll_with_capture,
+};
+
+/// Drive the future `fut` to completion, reporting its active await points if it runs longer than `threshold`.
+/// Unlike [`crate::timeout`], the future is not cancelled when `threshold` elapses: the traces are passed to `reporter`
+/// and `fut` keeps running until it completes.
+/// Use [`WatchdogFuture::new`] to choose a different [`Timer`] implementation,
+/// [`WatchdogFuture::repeat_every`] to keep reporting while the future is still running
+/// and [`WatchdogFuture::with_hard_limit`] to additionally cancel the future after some time.
+///
+/// # Examples
+/// ```rust
+/// # use std::time::Duration;
+/// # use tokio::time::sleep;
+/// # use timeout_tracing::{CaptureSpanTrace, LogStall, watchdog};
+/// # tokio::runtime::Runtime::new()
+/// #     .unwrap()
+/// #     .block_on(async {
+/// let result = watchdog(
+///     Duration::from_millis(10),
+///     CaptureSpanTrace,
+///     LogStall,
+///     async {
+///         sleep(Duration::from_millis(50)).await;
+///         42
+///     },
+/// )
+/// .await;
+/// assert_eq!(result, 42);
+/// # });
+/// ```
+pub fn watchdog<C, R, Fut>(
+    threshold: Duration,
+    capture: C,
+    reporter: R,
+    fut: Fut,
+) -> WatchdogFuture<C, R, Fut> {
+    WatchdogFuture::new(threshold, capture, reporter, fut)
+}
+
+pin_project! {
+    pub struct WatchdogFuture<C, R, Fut, T: Timer = DefaultTimer> {
+        #[pin]
+        threshold: T,
+        started: T::Instant,
+        capture: Arc<C>,
+        forwarded: Arc<ForwardedWakers>,
+        reporter: R,
+        interval: Option<Duration>,
+        snapshots: usize,
+        #[pin]
+        inner: Fut,
+    }
+}
+
+impl<C, R, Fut, T: Timer> WatchdogFuture<C, R, Fut, T> {
+    /// Same as [`watchdog`], but allows to choose the [`Timer`] implementation.
+    pub fn new(threshold: Duration, capture: C, reporter: R, fut: Fut) -> Self {
+        Self {
+            threshold: T::sleep(threshold),
+            started: T::now(),
+            capture: Arc::new(capture),
+            forwarded: Arc::default(),
+            reporter,
+            interval: None,
+            snapshots: 0,
+            inner: fut,
+        }
+    }
+
+    /// After the threshold elapses, keep capturing and reporting traces every `interval`
+    /// until the future completes.
+    #[must_use]
+    pub fn repeat_every(self, interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Register the future in the process-wide registry under `label`, so that it is reported by [`crate::dump_all`].
+    pub fn register(self, label: impl Into<String>) -> RegisteredFuture<Self> {
+        let elapsed = T::now() - self.started;
+        RegisteredFuture::new(label.into(), None, elapsed, self)
+    }
+}
+
+impl<C, R, Fut, T> WatchdogFuture<C, R, Fut, T>
+where
+    C: Clone,
+    T: Timer,
+{
+    /// Cancel the future if it fails to finish within `duration`, just like [`crate::timeout`] does.
+    /// The soft threshold of the watchdog is still reported to the reporter.
+    pub fn with_hard_limit(self, duration: Duration) -> TimeoutFuture<C, Self, T> {
+        let capture = C::clone(&self.capture);
+        TimeoutFuture::new(duration, capture, self)
+    }
+}
+
+impl<C, R, Fut, T> Future for WatchdogFuture<C, R, Fut, T>
+where
+    C: CaptureTrace + Send + 'static,
+    R: ReportStall<C::Trace>,
+    Fut: Future,
+    T: Timer,
+{
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let armed = *this.snapshots == 0 || this.interval.is_some();
+        if armed && this.threshold.as_mut().poll(cx).is_ready() {
+            // We hit the threshold. Poll the inner future while collecting the traces, but keep running it afterwards.
+            return match poll_with_capture(this.capture.clone(), this.forwarded, this.inner, cx) {
+                Ok(result) => Poll::Ready(result),
+                Err(active_traces) => {
+                    this.reporter.report(StallReport {
+                        snapshot: *this.snapshots,
+                        elapsed: T::now() - *this.started,
+                        active_traces,
+                    });
+                    *this.snapshots += 1;
+                    if let Some(interval) = this.interval {
+                        this.threshold.as_mut().reset(T::now() + *interval);
+                        // Register the waker for the new deadline
+                        _ = this.threshold.poll(cx);
+                    }
+                    Poll::Pending
+                }
+            };
+        }
+        let _forward_scope = ForwardScope::enter_if_needed(this.forwarded);
+        this.inner.poll(cx)
+    }
+}
+
+impl<C, R, Fut, T> CaptureNow for WatchdogFuture<C, R, Fut, T>
+where
+    C: CaptureTrace + Send + 'static,
+    R: ReportStall<C::Trace>,
+    Fut: Future,
+    T: Timer,
+{
+    type Trace = C::Trace;
+
+    fn capture_now(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Result<Self::Output, Vec<Self::Trace>> {
+        let this = self.project();
+        poll_with_capture(this.capture.clone(), this.forwarded, this.inner, cx)
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        // The watchdog does not cancel the future
+        None
+    }
+}
+
+/// A report about a future that runs longer than the watchdog threshold.
+#[derive(Debug)]
+pub struct StallReport<Trace> {
+    /// Sequential number of the report for the same future, starting from 0.
+    /// Reports after the first one are produced only with [`WatchdogFuture::repeat_every`].
+    pub snapshot: usize,
+    /// Time since the watchdog was created.
+    pub elapsed: Duration,
+    pub active_traces: Vec<Trace>,
+}
+
+impl<Trace: Display> Display for StallReport<Trace> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "future stalled for {:?}", self.elapsed)?;
+        if !self.active_traces.is_empty() {
+            f.write_str(" at:\n")?;
+            for (idx, trace) in self.active_traces.iter().enumerate() {
+                writeln!(f, "trace {idx}:\n{trace}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<Trace: crate::SerializeTrace> serde::Serialize for StallReport<Trace> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("StallReport", 3)?;
+        state.serialize_field("snapshot", &self.snapshot)?;
+        state.serialize_field("elapsed", &self.elapsed)?;
+        state.serialize_field(
+            "active_traces",
+            &crate::trace::SerializeAsTraces(&self.active_traces),
+        )?;
+        state.end()
+    }
+}
+
+/// A trait for handling [`StallReport`]s produced by [`watchdog`].
+/// It is implemented for closures accepting a [`StallReport`] and for [`std::sync::mpsc::Sender`].
+pub trait ReportStall<Trace> {
+    fn report(&mut self, report: StallReport<Trace>);
+}
+
+impl<Trace, F> ReportStall<Trace> for F
+where
+    F: FnMut(StallReport<Trace>),
+{
+    fn report(&mut self, report: StallReport<Trace>) {
+        self(report);
+    }
+}
+
+impl<Trace> ReportStall<Trace> for Sender<StallReport<Trace>> {
+    fn report(&mut self, report: StallReport<Trace>) {
+        // The receiver is not interested in reports anymore
+        _ = self.send(report);
+    }
+}
+
+/// Implementation of [`ReportStall`] that emits a `WARN` event via [`tracing`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogStall;
+
+impl<Trace: Display> ReportStall<Trace> for LogStall {
+    fn report(&mut self, report: StallReport<Trace>) {
+        warn!(snapshot = report.snapshot, elapsed = ?report.elapsed, "{report}");
+    }
+}